@@ -2,9 +2,9 @@ use criterion::{criterion_group, criterion_main, Criterion};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use lock_free_order_book::concurrent_queue::OrderQueue;
+use lock_free_order_book::engine::{Command, EngineConfig, ShardedEngine};
 use lock_free_order_book::order::{Order, Side};
 use lock_free_order_book::order_book::OrderBook;
-use std::time::Duration;
 
 fn bench_concurrent_order_book(c: &mut Criterion) {
     let producers = 4;
@@ -24,7 +24,7 @@ fn bench_concurrent_order_book(c: &mut Criterion) {
                         let id = (t * orders_per + i) as u64;
                         let order = Order::new(id, Side::Buy, 100, 1);
                         // spin until pushed
-                        while q.push(order.clone()).is_err() {}
+                        while q.push(order).is_err() {}
                     }
                 }));
             }
@@ -35,7 +35,7 @@ fn bench_concurrent_order_book(c: &mut Criterion) {
                 let mut count = 0;
                 while count < total {
                     if let Some(order) = q_consumer.pop() {
-                        book_consumer.lock().unwrap().add_order(order);
+                        book_consumer.lock().unwrap().add_order(order).unwrap();
                         count += 1;
                     } else {
                         thread::yield_now();
@@ -51,5 +51,44 @@ fn bench_concurrent_order_book(c: &mut Criterion) {
     });
 }
 
-criterion_group!(concurrent_order_book_benches, bench_concurrent_order_book);
+fn bench_sharded_engine(c: &mut Criterion) {
+    let producers = 4;
+    let orders_per = 10_000;
+    let shards = 4;
+
+    c.bench_function("sharded_engine_4x10k_4_shards", move |b| {
+        b.iter(|| {
+            let books = (0..shards)
+                .map(|i| (format!("SYM{}", i), OrderBook::new()))
+                .collect();
+            let config = EngineConfig {
+                shards,
+                queue_capacity: producers * orders_per,
+                pin_cores: None,
+                tick_interval: None,
+            };
+            let engine = ShardedEngine::start(books, config);
+            let symbols: Vec<_> = (0..shards)
+                .map(|i| engine.symbol_id(&format!("SYM{}", i)).unwrap())
+                .collect();
+            thread::scope(|scope| {
+                for t in 0..producers {
+                    let engine = &engine;
+                    let symbols = &symbols;
+                    scope.spawn(move || {
+                        for i in 0..orders_per {
+                            let id = (t * orders_per + i) as u64;
+                            let symbol = symbols[i % symbols.len()];
+                            let order = Order::new(id, Side::Buy, 100, 1);
+                            while engine.submit(symbol, Command::Add(order)).is_err() {}
+                        }
+                    });
+                }
+            });
+            engine.shutdown();
+        });
+    });
+}
+
+criterion_group!(concurrent_order_book_benches, bench_concurrent_order_book, bench_sharded_engine);
 criterion_main!(concurrent_order_book_benches);
@@ -1,6 +1,7 @@
 use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId};
 use lock_free_order_book::order::{Order, Side};
 use lock_free_order_book::order_book::OrderBook;
+use lock_free_order_book::units::Qty;
 use rand::prelude::*;
 
 fn setup_book_with_orders(n: u32) -> (OrderBook, Vec<Order>) {
@@ -16,7 +17,7 @@ fn setup_book_with_orders(n: u32) -> (OrderBook, Vec<Order>) {
         .collect();
 
     for order in &orders {
-        book.add_order(black_box(*order));
+        let _ = book.add_order(black_box(*order));
     }
 
     (book, orders)
@@ -27,7 +28,7 @@ fn benchmark_add_order(c: &mut Criterion) {
         b.iter(|| {
             let mut book = OrderBook::new();
             for i in 0..10_000 {
-                book.add_order(black_box(Order::new(i, Side::Buy, 100, 10)));
+                book.add_order(black_box(Order::new(i, Side::Buy, 100, 10))).unwrap();
             }
         })
     });
@@ -41,7 +42,7 @@ fn benchmark_cancel_order(c: &mut Criterion) {
         b.iter(|| {
             for _ in 0..1_000 {
                 let order_to_cancel = orders.choose(&mut rng).unwrap();
-                book.cancel_order(black_box(order_to_cancel.order_id), black_box(order_to_cancel.side), black_box(order_to_cancel.price));
+                let _ = book.cancel_order(black_box(order_to_cancel.order_id), black_box(order_to_cancel.side), black_box(order_to_cancel.price));
             }
         })
     });
@@ -55,8 +56,8 @@ fn benchmark_modify_order(c: &mut Criterion) {
         b.iter(|| {
             for _ in 0..1_000 {
                 let order_to_modify = orders.choose(&mut rng).unwrap();
-                let new_quantity = rng.gen_range(1..200);
-                book.modify_order(black_box(order_to_modify.order_id), black_box(order_to_modify.side), black_box(order_to_modify.price), black_box(new_quantity));
+                let new_quantity = Qty::new(rng.gen_range(1..200));
+                let _ = book.modify_order(black_box(order_to_modify.order_id), black_box(order_to_modify.side), black_box(order_to_modify.price), black_box(new_quantity));
             }
         })
     });
@@ -69,16 +70,15 @@ fn benchmark_matching_engine(c: &mut Criterion) {
             b.iter_with_setup(
                 || {
                     let mut book = OrderBook::new();
-                    let mut rng = StdRng::seed_from_u64(42);
                     // Pre-fill the book with ask orders
                     for i in 0..size {
-                        book.add_order(Order::new(i, Side::Sell, 100 + (i % 10) as u64, 10));
+                        book.add_order(Order::new(i, Side::Sell, 100 + (i % 10) as i64, 10)).unwrap();
                     }
                     book
                 },
                 |mut book| {
                     // Send a sweeping buy order to match
-                    book.add_order(black_box(Order::new(size, Side::Buy, 110, size * 10)));
+                    book.add_order(black_box(Order::new(size, Side::Buy, 110, size * 10))).unwrap();
                 },
             );
         });
@@ -0,0 +1,236 @@
+//! How an incoming order's quantity is shared among the resting orders of
+//! one price level.
+use crate::order::{Order, BASIS_POINTS_PER_UNIT};
+use crate::units::Qty;
+
+pub trait AllocationPolicy: Send + Sync {
+    /// Splits `quantity` across `resting`, which yields the level's orders in
+    /// time priority. Returns one allocation per order for a prefix of the
+    /// level (later orders get nothing). Each allocation is at most the
+    /// order's displayed quantity, and together they add up to `quantity`
+    /// or the level's displayed total, whichever is smaller.
+    fn allocate(&self, quantity: Qty, resting: &mut dyn Iterator<Item = &Order>) -> Vec<Qty>;
+}
+
+/// Strict time priority: the oldest order fills first.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Fifo;
+
+impl AllocationPolicy for Fifo {
+    fn allocate(&self, mut quantity: Qty, resting: &mut dyn Iterator<Item = &Order>) -> Vec<Qty> {
+        let mut allocations = Vec::new();
+        for order in resting {
+            if quantity.is_zero() {
+                break;
+            }
+            let fill = quantity.min(order.quantity);
+            allocations.push(fill);
+            quantity -= fill;
+        }
+        allocations
+    }
+}
+
+/// Who receives the lots left over after pro-rata shares are rounded down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remainder {
+    TimePriority,
+    /// Largest resting order first, ties by time priority.
+    LargestOrder,
+}
+
+/// Each order gets `quantity * size / level size`, rounded down. Shares
+/// below `min_allocation` are dropped, and whatever is left is handed out
+/// according to `remainder`, each order up to its unfilled size.
+#[derive(Debug, Clone, Copy)]
+pub struct ProRata {
+    pub min_allocation: u64,
+    pub remainder: Remainder,
+}
+
+impl ProRata {
+    fn allocate_sizes(&self, quantity: u64, sizes: &[u64]) -> Vec<u64> {
+        let total: u128 = sizes.iter().map(|&size| size as u128).sum();
+        if quantity as u128 >= total {
+            return sizes.to_vec();
+        }
+        let mut allocations: Vec<u64> = sizes
+            .iter()
+            .map(|&size| (quantity as u128 * size as u128 / total) as u64)
+            .map(|share| if share < self.min_allocation { 0 } else { share })
+            .collect();
+
+        let mut order: Vec<usize> = (0..sizes.len()).collect();
+        if self.remainder == Remainder::LargestOrder {
+            order.sort_by_key(|&i| std::cmp::Reverse(sizes[i]));
+        }
+        let mut leftover = quantity - allocations.iter().sum::<u64>();
+        for i in order {
+            if leftover == 0 {
+                break;
+            }
+            let extra = leftover.min(sizes[i] - allocations[i]);
+            allocations[i] += extra;
+            leftover -= extra;
+        }
+        allocations
+    }
+}
+
+impl AllocationPolicy for ProRata {
+    fn allocate(&self, quantity: Qty, resting: &mut dyn Iterator<Item = &Order>) -> Vec<Qty> {
+        let sizes: Vec<u64> = resting.map(|order| order.quantity.raw()).collect();
+        to_quantities(self.allocate_sizes(quantity.raw(), &sizes))
+    }
+}
+
+/// The order at the front of the queue fills first, up to `top_order_max`
+/// if set; the rest is shared pro-rata across the level.
+#[derive(Debug, Clone, Copy)]
+pub struct TopOrderProRata {
+    pub top_order_max: Option<u64>,
+    pub pro_rata: ProRata,
+}
+
+impl AllocationPolicy for TopOrderProRata {
+    fn allocate(&self, quantity: Qty, resting: &mut dyn Iterator<Item = &Order>) -> Vec<Qty> {
+        let quantity = quantity.raw();
+        let mut sizes: Vec<u64> = resting.map(|order| order.quantity.raw()).collect();
+        let Some(top_size) = sizes.first_mut() else { return Vec::new() };
+        let top = quantity
+            .min(*top_size)
+            .min(self.top_order_max.unwrap_or(u64::MAX));
+        *top_size -= top;
+
+        let mut allocations = self.pro_rata.allocate_sizes(quantity - top, &sizes);
+        allocations[0] += top;
+        to_quantities(allocations)
+    }
+}
+
+/// Lead market makers share up to `lmm_share_bps` of the incoming quantity
+/// in time priority among themselves; the rest fills the whole level FIFO.
+#[derive(Debug, Clone, Default)]
+pub struct FifoWithLmm {
+    pub lmm_accounts: Vec<u64>,
+    pub lmm_share_bps: u64,
+}
+
+impl AllocationPolicy for FifoWithLmm {
+    fn allocate(&self, quantity: Qty, resting: &mut dyn Iterator<Item = &Order>) -> Vec<Qty> {
+        let orders: Vec<&Order> = resting.collect();
+        let mut allocations = vec![Qty::ZERO; orders.len()];
+
+        let share = quantity.raw() as u128 * self.lmm_share_bps as u128 / BASIS_POINTS_PER_UNIT as u128;
+        let mut lmm_budget = Qty::new(share.min(quantity.raw() as u128) as u64);
+        for (allocation, order) in allocations.iter_mut().zip(&orders) {
+            if self.lmm_accounts.contains(&order.account_id) {
+                *allocation = lmm_budget.min(order.quantity);
+                lmm_budget -= *allocation;
+            }
+        }
+
+        let mut leftover = quantity - allocations.iter().copied().sum::<Qty>();
+        for (allocation, order) in allocations.iter_mut().zip(&orders) {
+            let extra = leftover.min(order.quantity - *allocation);
+            *allocation += extra;
+            leftover -= extra;
+        }
+        allocations
+    }
+}
+
+fn to_quantities(allocations: Vec<u64>) -> Vec<Qty> {
+    allocations.into_iter().map(Qty::new).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::order::Side;
+
+    fn level(sizes: &[(u64, u64)]) -> Vec<Order> {
+        sizes
+            .iter()
+            .enumerate()
+            .map(|(i, &(quantity, account))| {
+                Order::new(i as u64 + 1, Side::Sell, 100, quantity).with_account(account)
+            })
+            .collect()
+    }
+
+    fn allocate(policy: &dyn AllocationPolicy, quantity: u64, orders: &[Order]) -> Vec<u64> {
+        policy
+            .allocate(Qty::new(quantity), &mut orders.iter())
+            .into_iter()
+            .map(Qty::raw)
+            .collect()
+    }
+
+    #[test]
+    fn fifo_fills_a_prefix_of_the_queue() {
+        let orders = level(&[(5, 0), (5, 0), (5, 0)]);
+        assert_eq!(allocate(&Fifo, 7, &orders), vec![5, 2]);
+        assert_eq!(allocate(&Fifo, 20, &orders), vec![5, 5, 5]);
+    }
+
+    #[test]
+    fn pro_rata_rounds_down_and_hands_out_remainder() {
+        let orders = level(&[(10, 0), (30, 0), (60, 0)]);
+        let policy = ProRata { min_allocation: 0, remainder: Remainder::TimePriority };
+        assert_eq!(allocate(&policy, 50, &orders), vec![5, 15, 30]);
+        assert_eq!(allocate(&policy, 11, &orders), vec![2, 3, 6]);
+
+        let largest = ProRata { min_allocation: 0, remainder: Remainder::LargestOrder };
+        assert_eq!(allocate(&largest, 11, &orders), vec![1, 3, 7]);
+    }
+
+    #[test]
+    fn pro_rata_drops_shares_below_minimum() {
+        let orders = level(&[(10, 0), (30, 0), (60, 0)]);
+        let policy = ProRata { min_allocation: 2, remainder: Remainder::LargestOrder };
+        assert_eq!(allocate(&policy, 10, &orders), vec![0, 3, 7]);
+        assert_eq!(allocate(&policy, 100, &orders), vec![10, 30, 60]);
+    }
+
+    #[test]
+    fn top_order_fills_before_pro_rata() {
+        let orders = level(&[(20, 0), (40, 0), (40, 0)]);
+        let pro_rata = ProRata { min_allocation: 0, remainder: Remainder::TimePriority };
+        let uncapped = TopOrderProRata { top_order_max: None, pro_rata };
+        assert_eq!(allocate(&uncapped, 40, &orders), vec![20, 10, 10]);
+
+        let capped = TopOrderProRata { top_order_max: Some(10), pro_rata };
+        assert_eq!(allocate(&capped, 40, &orders), vec![14, 13, 13]);
+    }
+
+    #[test]
+    fn lmm_share_comes_before_fifo() {
+        let orders = level(&[(10, 1), (10, 9), (10, 2)]);
+        let policy = FifoWithLmm { lmm_accounts: vec![9], lmm_share_bps: 4_000 };
+        assert_eq!(allocate(&policy, 10, &orders), vec![6, 4, 0]);
+        assert_eq!(allocate(&policy, 25, &orders), vec![10, 10, 5]);
+    }
+
+    #[test]
+    fn pro_rata_handles_levels_above_u64_max() {
+        let orders = level(&[(u64::MAX, 0), (u64::MAX, 0)]);
+        let policy = ProRata { min_allocation: 0, remainder: Remainder::TimePriority };
+        assert_eq!(allocate(&policy, 11, &orders), vec![6, 5]);
+        assert_eq!(allocate(&policy, u64::MAX, &orders), vec![u64::MAX / 2 + 1, u64::MAX / 2]);
+
+        let top_order = TopOrderProRata { top_order_max: Some(1), pro_rata: policy };
+        assert_eq!(allocate(&top_order, 11, &orders), vec![6, 5]);
+    }
+
+    #[test]
+    fn lmm_share_of_huge_quantities_does_not_overflow() {
+        let orders = level(&[(u64::MAX, 1), (u64::MAX, 9)]);
+        let policy = FifoWithLmm { lmm_accounts: vec![9], lmm_share_bps: 4_000 };
+        let share = (u64::MAX as u128 * 4_000 / 10_000) as u64;
+        assert_eq!(allocate(&policy, u64::MAX, &orders), vec![u64::MAX - share, share]);
+
+        let everything = FifoWithLmm { lmm_accounts: vec![9], lmm_share_bps: 20_000 };
+        assert_eq!(allocate(&everything, 10, &orders), vec![0, 10]);
+    }
+}
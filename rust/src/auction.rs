@@ -0,0 +1,114 @@
+//! Price discovery for call auctions.
+use std::cmp::Reverse;
+
+use crate::units::{Price, Qty};
+
+/// Where an auction would uncross right now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuctionQuote {
+    pub price: Price,
+    pub matched_quantity: Qty,
+    /// Buy quantity minus sell quantity willing to trade at `price`; the
+    /// side with the surplus is left partly unfilled.
+    pub imbalance: i128,
+}
+
+/// The clearing price for `bids` and `asks`, given as `(price, quantity)`
+/// in any order. Picks the price that matches the most quantity, then the
+/// one with the smallest imbalance, then the one closest to `reference`,
+/// then the lowest. `None` if nothing would trade.
+pub fn equilibrium(bids: &[(Price, Qty)], asks: &[(Price, Qty)], reference: Option<Price>) -> Option<AuctionQuote> {
+    let mut prices: Vec<Price> = bids.iter().chain(asks).map(|&(price, _)| price).collect();
+    prices.sort_unstable();
+    prices.dedup();
+    let slot = |price: Price| prices.binary_search(&price).expect("candidate price");
+
+    let mut demand = vec![Qty::ZERO; prices.len()];
+    for &(price, quantity) in bids {
+        demand[slot(price)] = demand[slot(price)].saturating_add(quantity);
+    }
+    for i in (1..prices.len()).rev() {
+        let later = demand[i];
+        demand[i - 1] = demand[i - 1].saturating_add(later);
+    }
+    let mut supply = vec![Qty::ZERO; prices.len()];
+    for &(price, quantity) in asks {
+        supply[slot(price)] = supply[slot(price)].saturating_add(quantity);
+    }
+    for i in 1..prices.len() {
+        let earlier = supply[i - 1];
+        supply[i] = supply[i].saturating_add(earlier);
+    }
+
+    prices
+        .iter()
+        .zip(demand.iter().zip(&supply))
+        .map(|(&price, (&buy, &sell))| AuctionQuote {
+            price,
+            matched_quantity: buy.min(sell),
+            imbalance: buy.raw() as i128 - sell.raw() as i128,
+        })
+        .filter(|quote| !quote.matched_quantity.is_zero())
+        .min_by_key(|quote| {
+            let distance = reference.map_or(0, |reference| quote.price.abs_diff(reference));
+            (Reverse(quote.matched_quantity), quote.imbalance.unsigned_abs(), distance, quote.price)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AuctionQuote, Price, Qty};
+
+    fn equilibrium(bids: &[(i64, u64)], asks: &[(i64, u64)], reference: Option<i64>) -> Option<AuctionQuote> {
+        let levels = |levels: &[(i64, u64)]| -> Vec<(Price, Qty)> {
+            levels.iter().map(|&(price, quantity)| (Price::new(price), Qty::new(quantity))).collect()
+        };
+        super::equilibrium(&levels(bids), &levels(asks), reference.map(Price::new))
+    }
+
+    fn quote(price: i64, matched_quantity: u64, imbalance: i128) -> AuctionQuote {
+        AuctionQuote { price: Price::new(price), matched_quantity: Qty::new(matched_quantity), imbalance }
+    }
+
+    #[test]
+    fn maximises_matched_volume() {
+        let bids = [(102, 5), (101, 5), (100, 10)];
+        let asks = [(99, 4), (100, 6), (101, 10)];
+        assert_eq!(
+            equilibrium(&bids, &asks, None),
+            Some(quote(100, 10, 10))
+        );
+    }
+
+    #[test]
+    fn smaller_imbalance_breaks_volume_ties() {
+        let bids = [(101, 10), (100, 5)];
+        let asks = [(100, 10), (101, 2)];
+        assert_eq!(
+            equilibrium(&bids, &asks, None),
+            Some(quote(101, 10, -2))
+        );
+    }
+
+    #[test]
+    fn reference_price_breaks_remaining_ties() {
+        let bids = [(105, 10)];
+        let asks = [(100, 10)];
+        assert_eq!(equilibrium(&bids, &asks, None).unwrap().price, 100);
+        assert_eq!(equilibrium(&bids, &asks, Some(104)).unwrap().price, 105);
+        assert_eq!(equilibrium(&bids, &asks, Some(90)).unwrap().price, 100);
+    }
+
+    #[test]
+    fn huge_quantities_keep_the_imbalance_sign() {
+        let bids = [(100, u64::MAX - 5), (99, 10)];
+        let asks = [(99, 1)];
+        assert_eq!(equilibrium(&bids, &asks, None), Some(quote(100, 1, u64::MAX as i128 - 6)));
+    }
+
+    #[test]
+    fn uncrossed_book_has_no_equilibrium() {
+        assert_eq!(equilibrium(&[(99, 10)], &[(100, 10)], Some(100)), None);
+        assert_eq!(equilibrium(&[], &[(100, 10)], None), None);
+    }
+}
@@ -0,0 +1,319 @@
+//! Many order books, one per symbol, behind a single venue.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::BookError;
+use crate::event::CancelEvent;
+use crate::instrument::{Instrument, ReferenceData};
+use crate::order::Order;
+use crate::order_book::OrderBook;
+use crate::session::SessionState;
+use crate::trade::Trade;
+use crate::units::{Price, Qty};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExchangeError {
+    UnknownSymbol(String),
+    DuplicateSymbol(String),
+    /// A book listed under a symbol other than its instrument's.
+    SymbolMismatch { symbol: String, instrument: String },
+    /// A book not running on the manager's clock.
+    ForeignClock(String),
+    Book(BookError),
+}
+
+impl From<BookError> for ExchangeError {
+    fn from(err: BookError) -> Self {
+        ExchangeError::Book(err)
+    }
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExchangeError::UnknownSymbol(symbol) => write!(f, "unknown symbol {}", symbol),
+            ExchangeError::DuplicateSymbol(symbol) => write!(f, "symbol {} is already listed", symbol),
+            ExchangeError::SymbolMismatch { symbol, instrument } => {
+                write!(f, "book for {} cannot be listed as {}", instrument, symbol)
+            }
+            ExchangeError::ForeignClock(symbol) => write!(f, "book for {} is not on the venue clock", symbol),
+            ExchangeError::Book(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ExchangeError {}
+
+/// Best bid and ask of one book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quote {
+    pub symbol: String,
+    pub bid: Option<Price>,
+    pub ask: Option<Price>,
+    pub last_trade_price: Option<Price>,
+}
+
+/// Owns a book per symbol and routes orders to them. All books share the
+/// manager's clock. Symbols are kept sorted, so aggregated queries come
+/// back in symbol order.
+pub struct BookManager {
+    books: BTreeMap<String, OrderBook>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for BookManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BookManager {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        BookManager {
+            books: BTreeMap::new(),
+            clock,
+        }
+    }
+
+    /// A manager listing every instrument in `reference_data`.
+    pub fn from_reference_data(reference_data: &ReferenceData, clock: Arc<dyn Clock>) -> Result<Self, ExchangeError> {
+        let mut manager = Self::with_clock(clock);
+        for instrument in &reference_data.instruments {
+            manager.list(instrument.clone())?;
+        }
+        Ok(manager)
+    }
+
+    /// Opens a book for `instrument` on the manager's clock.
+    pub fn list(&mut self, instrument: Instrument) -> Result<&mut OrderBook, ExchangeError> {
+        let symbol = instrument.symbol.clone();
+        let book = OrderBook::with_clock(self.clock.clone()).with_instrument(instrument)?;
+        self.insert_book(&symbol, book)
+    }
+
+    /// Adds a book built by the caller, e.g. with its own allocation
+    /// policy or price bands. The book must be on the manager's clock and,
+    /// if it has an instrument, be listed under the instrument's symbol.
+    pub fn insert_book(&mut self, symbol: &str, book: OrderBook) -> Result<&mut OrderBook, ExchangeError> {
+        if self.books.contains_key(symbol) {
+            return Err(ExchangeError::DuplicateSymbol(symbol.to_string()));
+        }
+        if let Some(instrument) = book.instrument().filter(|instrument| instrument.symbol != symbol) {
+            let instrument = instrument.symbol.clone();
+            return Err(ExchangeError::SymbolMismatch { symbol: symbol.to_string(), instrument });
+        }
+        if !Arc::ptr_eq(book.clock(), &self.clock) {
+            return Err(ExchangeError::ForeignClock(symbol.to_string()));
+        }
+        Ok(self.books.entry(symbol.to_string()).or_insert(book))
+    }
+
+    pub fn delist(&mut self, symbol: &str) -> Result<OrderBook, ExchangeError> {
+        self.books.remove(symbol).ok_or_else(|| unknown(symbol))
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&OrderBook> {
+        self.books.get(symbol)
+    }
+
+    pub fn book_mut(&mut self, symbol: &str) -> Option<&mut OrderBook> {
+        self.books.get_mut(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.books.keys().map(String::as_str)
+    }
+
+    pub fn add_order(&mut self, symbol: &str, order: Order) -> Result<Vec<Trade>, ExchangeError> {
+        Ok(self.routed(symbol)?.add_order(order)?)
+    }
+
+    pub fn cancel_order(&mut self, symbol: &str, order_id: u64) -> Result<Order, ExchangeError> {
+        Ok(self.routed(symbol)?.cancel_order_by_id(order_id)?)
+    }
+
+    pub fn modify_order(&mut self, symbol: &str, order_id: u64, new_quantity: Qty) -> Result<(), ExchangeError> {
+        Ok(self.routed(symbol)?.modify_order_by_id(order_id, new_quantity)?)
+    }
+
+    /// Top of book for every symbol.
+    pub fn quotes(&self) -> Vec<Quote> {
+        self.books
+            .iter()
+            .map(|(symbol, book)| Quote {
+                symbol: symbol.clone(),
+                bid: book.best_bid(),
+                ask: book.best_ask(),
+                last_trade_price: book.last_trade_price(),
+            })
+            .collect()
+    }
+
+    /// An account's non-zero positions, by symbol.
+    pub fn positions(&self, account_id: u64) -> Vec<(&str, i128)> {
+        self.books
+            .iter()
+            .map(|(symbol, book)| (symbol.as_str(), book.position(account_id)))
+            .filter(|&(_, position)| position != 0)
+            .collect()
+    }
+
+    /// Live orders across all books.
+    pub fn order_count(&self) -> usize {
+        self.books.values().map(OrderBook::order_count).sum()
+    }
+
+    /// Cancel events from every book, tagged with their symbol.
+    pub fn drain_cancels(&mut self) -> Vec<(String, CancelEvent)> {
+        let mut cancels = Vec::new();
+        for (symbol, book) in &mut self.books {
+            cancels.extend(book.drain_cancels().into_iter().map(|event| (symbol.clone(), event)));
+        }
+        cancels
+    }
+
+    /// Moves every book to `state`, or none of them if any book cannot
+    /// make the transition. Returns the trades of any opening uncross.
+    pub fn set_session_state(&mut self, state: SessionState) -> Result<Vec<(String, Trade)>, ExchangeError> {
+        if let Some(book) = self.books.values().find(|book| !book.session_state().can_transition_to(state)) {
+            return Err(BookError::InvalidTransition { from: book.session_state(), to: state }.into());
+        }
+        let mut trades = Vec::new();
+        for (symbol, book) in &mut self.books {
+            let fills = book.set_session_state(state).expect("transition checked above");
+            trades.extend(fills.into_iter().map(|trade| (symbol.clone(), trade)));
+        }
+        Ok(trades)
+    }
+
+    /// Applies due scheduled session transitions in every book.
+    pub fn poll_sessions(&mut self) -> Vec<(String, Trade)> {
+        let mut trades = Vec::new();
+        for (symbol, book) in &mut self.books {
+            trades.extend(book.poll_session().into_iter().map(|trade| (symbol.clone(), trade)));
+        }
+        trades
+    }
+
+    fn routed(&mut self, symbol: &str) -> Result<&mut OrderBook, ExchangeError> {
+        self.books.get_mut(symbol).ok_or_else(|| unknown(symbol))
+    }
+}
+
+fn unknown(symbol: &str) -> ExchangeError {
+    ExchangeError::UnknownSymbol(symbol.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BookManager, ExchangeError};
+    use crate::clock::{Clock, ManualClock};
+    use crate::error::BookError;
+    use crate::instrument::{Instrument, ReferenceData};
+    use crate::order::{Order, Side};
+    use crate::order_book::OrderBook;
+    use crate::session::SessionState;
+    use crate::units::{Price, Qty};
+    use std::sync::Arc;
+
+    fn venue() -> BookManager {
+        let reference_data = ReferenceData {
+            instruments: vec![
+                Instrument::new("MSFT"),
+                Instrument { lot_size: Qty::new(10), ..Instrument::new("AAPL") },
+            ],
+        };
+        BookManager::from_reference_data(&reference_data, Arc::new(ManualClock::new(0))).unwrap()
+    }
+
+    #[test]
+    fn routes_orders_to_the_symbol_book() {
+        let mut venue = venue();
+        venue.add_order("AAPL", Order::new(1, Side::Sell, 190, 10).with_account(7)).unwrap();
+        venue.add_order("MSFT", Order::new(1, Side::Sell, 410, 5)).unwrap();
+        let trades = venue.add_order("AAPL", Order::new(2, Side::Buy, 190, 10)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(venue.book("MSFT").unwrap().best_ask(), Some(Price::new(410)));
+
+        assert_eq!(
+            venue.add_order("AAPL", Order::new(3, Side::Buy, 190, 5)),
+            Err(ExchangeError::Book(BookError::QuantityNotOnLot {
+                order_id: 3,
+                quantity: Qty::new(5),
+                lot_size: Qty::new(10)
+            }))
+        );
+        assert_eq!(
+            venue.add_order("TSLA", Order::new(4, Side::Buy, 200, 1)),
+            Err(ExchangeError::UnknownSymbol("TSLA".to_string()))
+        );
+        assert_eq!(venue.cancel_order("MSFT", 1).unwrap().order_id, 1);
+        assert!(venue.cancel_order("AAPL", 1).is_err());
+    }
+
+    #[test]
+    fn aggregates_quotes_positions_and_counts() {
+        let mut venue = venue();
+        venue.add_order("MSFT", Order::new(1, Side::Buy, 400, 5)).unwrap();
+        venue.add_order("MSFT", Order::new(2, Side::Sell, 405, 5)).unwrap();
+        venue.add_order("AAPL", Order::new(3, Side::Sell, 190, 20).with_account(7)).unwrap();
+        venue.add_order("AAPL", Order::new(4, Side::Buy, 190, 10).with_account(8)).unwrap();
+
+        let quotes = venue.quotes();
+        let symbols: Vec<&str> = quotes.iter().map(|quote| quote.symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["AAPL", "MSFT"]);
+        assert_eq!((quotes[0].ask, quotes[0].last_trade_price), (Some(Price::new(190)), Some(Price::new(190))));
+        assert_eq!((quotes[1].bid, quotes[1].ask), (Some(Price::new(400)), Some(Price::new(405))));
+        assert_eq!(venue.positions(7), vec![("AAPL", -10)]);
+        assert_eq!(venue.order_count(), 3);
+        assert_eq!(
+            venue.list(Instrument::new("MSFT")).err(),
+            Some(ExchangeError::DuplicateSymbol("MSFT".to_string()))
+        );
+        let no_lot = Instrument { lot_size: Qty::ZERO, ..Instrument::new("TSLA") };
+        assert_eq!(venue.list(no_lot).err(), Some(ExchangeError::Book(BookError::InvalidInstrument)));
+    }
+
+    #[test]
+    fn inserted_books_share_the_clock_and_match_their_symbol() {
+        let clock: Arc<dyn Clock> = Arc::new(ManualClock::new(0));
+        let mut venue = BookManager::with_clock(clock.clone());
+        let nvda = || OrderBook::with_clock(clock.clone()).with_instrument(Instrument::new("NVDA")).unwrap();
+        assert_eq!(
+            venue.insert_book("AMD", nvda()).err(),
+            Some(ExchangeError::SymbolMismatch { symbol: "AMD".to_string(), instrument: "NVDA".to_string() })
+        );
+        assert_eq!(venue.insert_book("AMD", OrderBook::new()).err(), Some(ExchangeError::ForeignClock("AMD".to_string())));
+        venue.insert_book("NVDA", nvda()).unwrap();
+        venue.insert_book("AMD", OrderBook::with_clock(clock)).unwrap();
+        assert_eq!(venue.symbols().collect::<Vec<_>>(), vec!["AMD", "NVDA"]);
+    }
+
+    #[test]
+    fn session_changes_apply_to_every_book() {
+        let mut venue = venue();
+        venue.set_session_state(SessionState::Halted).unwrap();
+        assert!(venue.add_order("MSFT", Order::new(1, Side::Buy, 400, 5)).is_err());
+        assert!(venue.add_order("AAPL", Order::new(2, Side::Buy, 190, 10)).is_err());
+    }
+
+    #[test]
+    fn session_changes_leave_every_book_alone_if_one_cannot_move() {
+        let mut venue = venue();
+        venue.book_mut("MSFT").unwrap().set_session_state(SessionState::Closed).unwrap();
+        assert_eq!(
+            venue.set_session_state(SessionState::Halted),
+            Err(ExchangeError::Book(BookError::InvalidTransition {
+                from: SessionState::Closed,
+                to: SessionState::Halted
+            }))
+        );
+        assert_eq!(venue.book("AAPL").unwrap().session_state(), SessionState::Continuous);
+    }
+}
@@ -0,0 +1,111 @@
+//! Time sources for event timestamps and order expiry.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub const NANOS_PER_MILLI: u64 = 1_000_000;
+
+/// Nanosecond time source injected into the book, the simulator and the
+/// websocket server, so every event time comes from one place.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+}
+
+/// Wall-clock nanoseconds since the Unix epoch.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64
+    }
+}
+
+/// Epoch nanoseconds that never go backwards: anchored to the wall clock
+/// once, then advanced by the monotonic counter (the TSC on most x86
+/// hosts), so NTP slews and steps do not reorder events.
+#[derive(Debug, Clone, Copy)]
+pub struct MonotonicClock {
+    origin_nanos: u64,
+    origin: Instant,
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        MonotonicClock {
+            origin_nanos: SystemClock.now(),
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> u64 {
+        self.origin_nanos + self.origin.elapsed().as_nanos() as u64
+    }
+}
+
+/// A clock that only moves when told to, for tests and replays. Relaxed
+/// ordering is enough since the clock value guards no other memory.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    nanos: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(start: u64) -> Self {
+        ManualClock {
+            nanos: AtomicU64::new(start),
+        }
+    }
+
+    pub fn set(&self, nanos: u64) {
+        self.nanos.store(nanos, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, nanos: u64) {
+        self.nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.nanos.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clock, ManualClock, MonotonicClock, SystemClock};
+
+    #[test]
+    fn monotonic_clock_starts_at_wall_time_and_never_goes_back() {
+        let before = SystemClock.now();
+        let clock = MonotonicClock::new();
+        let mut last = clock.now();
+        assert!(last >= before);
+        for _ in 0..1_000 {
+            let now = clock.now();
+            assert!(now >= last);
+            last = now;
+        }
+    }
+
+    #[test]
+    fn manual_clock_moves_only_when_told() {
+        let clock = ManualClock::new(5);
+        assert_eq!(clock.now(), 5);
+        clock.advance(10);
+        assert_eq!(clock.now(), 15);
+        clock.set(3);
+        assert_eq!(clock.now(), 3);
+    }
+}
@@ -3,25 +3,43 @@ use std::sync::Arc;
 
 use crate::order::Order;
 
-#[derive(Clone)]
-pub struct OrderQueue {
-    inner: Arc<ArrayQueue<Order>>,
+/// Bounded lock-free MPMC queue of orders, or of any other message `T`.
+pub struct OrderQueue<T = Order> {
+    inner: Arc<ArrayQueue<T>>,
 }
 
-impl OrderQueue {
+impl<T> Clone for OrderQueue<T> {
+    fn clone(&self) -> Self {
+        OrderQueue {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> OrderQueue<T> {
     pub fn new(capacity: usize) -> Self {
         OrderQueue {
             inner: Arc::new(ArrayQueue::new(capacity)),
         }
     }
 
-    pub fn push(&self, order: Order) -> Result<(), Order> {
-        self.inner.push(order)
+    /// Hands the item back when the queue is full, like `ArrayQueue::push`.
+    #[allow(clippy::result_large_err)]
+    pub fn push(&self, item: T) -> Result<(), T> {
+        self.inner.push(item)
     }
 
-    pub fn pop(&self) -> Option<Order> {
+    pub fn pop(&self) -> Option<T> {
         self.inner.pop()
     }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -64,7 +82,7 @@ mod tests {
                 for i in 0..orders_per {
                     let id = (t * orders_per + i) as u64;
                     let order = Order::new(id, Side::Sell, 100, 1);
-                    while producer.push(order.clone()).is_err() {}
+                    while producer.push(order).is_err() {}
                 }
             }));
         }
@@ -90,7 +108,7 @@ mod tests {
             handles.push(thread::spawn(move || {
                 for i in 0..orders_per {
                     let order = Order::new((t * orders_per + i) as u64, Side::Buy, 100, 1);
-                    while q.push(order.clone()).is_err() {}
+                    while q.push(order).is_err() {}
                 }
             }));
         }
@@ -98,7 +116,7 @@ mod tests {
         let mut book = OrderBook::new();
         let mut count = 0;
         while let Some(order) = queue.pop() {
-            book.add_order(order);
+            book.add_order(order).unwrap();
             count += 1;
         }
         assert_eq!(count, producers * orders_per);
@@ -0,0 +1,493 @@
+//! Sharded matching runtime: symbols are spread over dedicated matching
+//! threads, each owning its books outright and fed through its own
+//! lock-free queue, so the hot path takes no locks.
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crossbeam::queue::SegQueue;
+use crossbeam::utils::Backoff;
+
+use crate::concurrent_queue::OrderQueue;
+use crate::error::BookError;
+use crate::event::CancelEvent;
+use crate::market_data::OrderEvent;
+use crate::order::Order;
+use crate::order_book::OrderBook;
+use crate::trade::Trade;
+use crate::units::Qty;
+
+/// Index of a symbol in the engine, as given to `ShardedEngine::start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SymbolId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Add(Order),
+    Cancel { order_id: u64 },
+    Modify { order_id: u64, quantity: Qty },
+    /// Expires due good-till-date orders and applies due session
+    /// transitions now, without waiting for the shard's next tick.
+    Tick,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Request {
+    pub symbol: SymbolId,
+    pub command: Command,
+}
+
+/// Why `ShardedEngine::submit` did not queue a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitError {
+    /// The symbol was not issued by this engine.
+    UnknownSymbol(SymbolId),
+    /// The shard's input queue is full; the request is handed back.
+    QueueFull(Request),
+}
+
+/// What a matching thread reports back, per symbol in processing order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionEvent {
+    Accepted { symbol: SymbolId, order_id: u64 },
+    Rejected { symbol: SymbolId, order_id: u64, error: BookError },
+    Trade { symbol: SymbolId, trade: Trade },
+    Modified { symbol: SymbolId, order_id: u64, quantity: Qty },
+    Cancelled { symbol: SymbolId, event: CancelEvent },
+    /// L3 feed, from books built `with_order_events`.
+    Order { symbol: SymbolId, event: OrderEvent },
+}
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub shards: usize,
+    /// Capacity of each shard's input queue.
+    pub queue_capacity: usize,
+    /// Cores to pin shard threads to, shard `i` on `cores[i % len]`.
+    /// Pinning is best effort and only done on Linux.
+    pub pin_cores: Option<Vec<usize>>,
+    /// How often each shard ticks its books, as `Command::Tick` does.
+    /// `None` leaves ticking to explicit commands.
+    pub tick_interval: Option<Duration>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            shards: 1,
+            queue_capacity: 65_536,
+            pin_cores: None,
+            tick_interval: Some(Duration::from_millis(10)),
+        }
+    }
+}
+
+struct Shard {
+    input: OrderQueue<Request>,
+    output: Arc<SegQueue<ExecutionEvent>>,
+    thread: JoinHandle<Vec<OrderBook>>,
+}
+
+/// Symbol `i` is matched on shard `i % shards`. Each shard's output queue
+/// is unbounded, so events pile up until drained.
+pub struct ShardedEngine {
+    symbols: HashMap<String, SymbolId>,
+    names: Vec<String>,
+    shards: Vec<Shard>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ShardedEngine {
+    pub fn start(books: Vec<(String, OrderBook)>, config: EngineConfig) -> Self {
+        let shard_count = config.shards.max(1);
+        let names: Vec<String> = books.iter().map(|(name, _)| name.clone()).collect();
+        let symbols = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.clone(), SymbolId(i)))
+            .collect();
+
+        let mut grouped: Vec<Vec<OrderBook>> = (0..shard_count).map(|_| Vec::new()).collect();
+        for (i, (_, book)) in books.into_iter().enumerate() {
+            grouped[i % shard_count].push(book);
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let shards = grouped
+            .into_iter()
+            .enumerate()
+            .map(|(shard, books)| {
+                let input = OrderQueue::new(config.queue_capacity);
+                let output = Arc::new(SegQueue::new());
+                let core = config
+                    .pin_cores
+                    .as_ref()
+                    .filter(|cores| !cores.is_empty())
+                    .map(|cores| cores[shard % cores.len()]);
+                let worker = Worker {
+                    books,
+                    shard,
+                    shards: shard_count,
+                    input: input.clone(),
+                    output: output.clone(),
+                    stop: stop.clone(),
+                    tick_interval: config.tick_interval,
+                };
+                let thread = thread::Builder::new()
+                    .name(format!("matching-{}", shard))
+                    .spawn(move || {
+                        if let Some(core) = core {
+                            pin_to_core(core);
+                        }
+                        worker.run()
+                    })
+                    .expect("spawn matching thread");
+                Shard { input, output, thread }
+            })
+            .collect();
+
+        ShardedEngine { symbols, names, shards, stop }
+    }
+
+    pub fn symbol_id(&self, symbol: &str) -> Option<SymbolId> {
+        self.symbols.get(symbol).copied()
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Queues `command` for its symbol's shard. Requests for one symbol are
+    /// processed in the order they were queued.
+    #[allow(clippy::result_large_err)]
+    pub fn submit(&self, symbol: SymbolId, command: Command) -> Result<(), SubmitError> {
+        if symbol.0 >= self.names.len() {
+            return Err(SubmitError::UnknownSymbol(symbol));
+        }
+        let shard = &self.shards[symbol.0 % self.shards.len()];
+        shard.input.push(Request { symbol, command }).map_err(SubmitError::QueueFull)
+    }
+
+    /// Takes every event the shards have produced so far. Events from one
+    /// shard keep their order; shards are drained one after another.
+    pub fn drain_events(&self) -> Vec<ExecutionEvent> {
+        let mut events = Vec::new();
+        for shard in &self.shards {
+            while let Some(event) = shard.output.pop() {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Lets every shard finish the requests already queued, stops the
+    /// threads and hands the books back with any undrained events.
+    pub fn shutdown(self) -> (Vec<(String, OrderBook)>, Vec<ExecutionEvent>) {
+        self.stop.store(true, Ordering::Release);
+        let shard_count = self.shards.len();
+        let mut by_shard = Vec::new();
+        let mut events = Vec::new();
+        for shard in self.shards {
+            let books = shard.thread.join().expect("matching thread panicked");
+            while let Some(event) = shard.output.pop() {
+                events.push(event);
+            }
+            by_shard.push(books.into_iter());
+        }
+        let books = self
+            .names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, by_shard[i % shard_count].next().expect("book per symbol")))
+            .collect();
+        (books, events)
+    }
+}
+
+struct Worker {
+    /// This shard's books; symbol `i` is at `i / shards`.
+    books: Vec<OrderBook>,
+    shard: usize,
+    shards: usize,
+    input: OrderQueue<Request>,
+    output: Arc<SegQueue<ExecutionEvent>>,
+    stop: Arc<AtomicBool>,
+    tick_interval: Option<Duration>,
+}
+
+impl Worker {
+    fn run(mut self) -> Vec<OrderBook> {
+        let backoff = Backoff::new();
+        let mut next_tick = self.tick_interval.map(|interval| Instant::now() + interval);
+        loop {
+            if let (Some(due), Some(interval)) = (next_tick, self.tick_interval) {
+                if Instant::now() >= due {
+                    for i in 0..self.books.len() {
+                        self.tick(i);
+                    }
+                    next_tick = Some(Instant::now() + interval);
+                }
+            }
+            if let Some(request) = self.input.pop() {
+                self.handle(request);
+                backoff.reset();
+            } else if self.stop.load(Ordering::Acquire) {
+                while let Some(request) = self.input.pop() {
+                    self.handle(request);
+                }
+                return self.books;
+            } else {
+                backoff.snooze();
+            }
+        }
+    }
+
+    fn handle(&mut self, Request { symbol, command }: Request) {
+        let book = &mut self.books[symbol.0 / self.shards];
+        let output = &self.output;
+        match command {
+            Command::Add(order) => match book.add_order(order) {
+                Ok(trades) => {
+                    output.push(ExecutionEvent::Accepted { symbol, order_id: order.order_id });
+                    for trade in trades {
+                        output.push(ExecutionEvent::Trade { symbol, trade });
+                    }
+                }
+                Err(error) => output.push(ExecutionEvent::Rejected { symbol, order_id: order.order_id, error }),
+            },
+            Command::Cancel { order_id } => {
+                if let Err(error) = book.cancel_order_by_id(order_id) {
+                    output.push(ExecutionEvent::Rejected { symbol, order_id, error });
+                }
+            }
+            Command::Modify { order_id, quantity } => match book.modify_order_by_id(order_id, quantity) {
+                Ok(()) => output.push(ExecutionEvent::Modified { symbol, order_id, quantity }),
+                Err(error) => output.push(ExecutionEvent::Rejected { symbol, order_id, error }),
+            },
+            Command::Tick => {
+                self.tick(symbol.0 / self.shards);
+                return;
+            }
+        }
+        self.publish(symbol.0 / self.shards);
+    }
+
+    /// Runs the time-driven work of the book at `index` and reports it.
+    fn tick(&mut self, index: usize) {
+        let book = &mut self.books[index];
+        book.expire_orders();
+        let symbol = SymbolId(index * self.shards + self.shard);
+        for trade in book.poll_session() {
+            self.output.push(ExecutionEvent::Trade { symbol, trade });
+        }
+        self.publish(index);
+    }
+
+    /// Forwards the cancel and L3 events the book at `index` has collected.
+    fn publish(&mut self, index: usize) {
+        let book = &mut self.books[index];
+        let symbol = SymbolId(index * self.shards + self.shard);
+        let output = &self.output;
+        for event in book.drain_cancels() {
+            output.push(ExecutionEvent::Cancelled { symbol, event });
+        }
+        for event in book.drain_order_events() {
+            output.push(ExecutionEvent::Order { symbol, event });
+        }
+    }
+}
+
+/// Pins the calling thread to `core`; returns whether it worked.
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) -> bool {
+    // SAFETY: `set` is a plain bitmask owned by this frame, and
+    // sched_setaffinity only reads it.
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(core, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{Command, EngineConfig, ExecutionEvent, ShardedEngine, SubmitError, SymbolId};
+    use crate::clock::ManualClock;
+    use crate::error::BookError;
+    use crate::event::CancelReason;
+    use crate::market_data::{OrderEventKind, RestingOrder};
+    use crate::order::{Order, Side, TimeInForce};
+    use crate::order_book::OrderBook;
+    use crate::session::SessionState;
+    use crate::units::{Price, Qty};
+
+    fn engine(shards: usize, pin_cores: Option<Vec<usize>>) -> ShardedEngine {
+        let books = ["AAPL", "MSFT", "TSLA"]
+            .iter()
+            .map(|symbol| (symbol.to_string(), OrderBook::new()))
+            .collect();
+        ShardedEngine::start(books, EngineConfig { shards, queue_capacity: 1_024, pin_cores, tick_interval: None })
+    }
+
+    fn for_symbol(events: &[ExecutionEvent], wanted: SymbolId) -> Vec<ExecutionEvent> {
+        events
+            .iter()
+            .copied()
+            .filter(|event| {
+                let symbol = match *event {
+                    ExecutionEvent::Accepted { symbol, .. }
+                    | ExecutionEvent::Rejected { symbol, .. }
+                    | ExecutionEvent::Trade { symbol, .. }
+                    | ExecutionEvent::Modified { symbol, .. }
+                    | ExecutionEvent::Cancelled { symbol, .. }
+                    | ExecutionEvent::Order { symbol, .. } => symbol,
+                };
+                symbol == wanted
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shards_match_each_symbol_independently() {
+        let engine = engine(2, Some(vec![0]));
+        assert_eq!(engine.shard_count(), 2);
+        let aapl = engine.symbol_id("AAPL").unwrap();
+        let tsla = engine.symbol_id("TSLA").unwrap();
+        for symbol in [aapl, tsla] {
+            engine.submit(symbol, Command::Add(Order::new(1, Side::Sell, 100, 5))).unwrap();
+            engine.submit(symbol, Command::Add(Order::new(2, Side::Buy, 100, 3))).unwrap();
+        }
+        engine.submit(aapl, Command::Modify { order_id: 1, quantity: Qty::new(1) }).unwrap();
+        engine.submit(tsla, Command::Cancel { order_id: 1 }).unwrap();
+        engine.submit(tsla, Command::Cancel { order_id: 1 }).unwrap();
+
+        let (books, events) = engine.shutdown();
+        let aapl_events = for_symbol(&events, aapl);
+        assert_eq!(aapl_events.len(), 4);
+        assert!(matches!(aapl_events[2], ExecutionEvent::Trade { trade, .. } if trade.quantity == 3));
+        assert_eq!(aapl_events[3], ExecutionEvent::Modified { symbol: aapl, order_id: 1, quantity: Qty::new(1) });
+
+        let tsla_events = for_symbol(&events, tsla);
+        assert!(matches!(
+            tsla_events[3],
+            ExecutionEvent::Cancelled { event, .. } if event.reason == CancelReason::Requested && event.quantity == 2
+        ));
+        assert_eq!(
+            tsla_events[4],
+            ExecutionEvent::Rejected { symbol: tsla, order_id: 1, error: BookError::UnknownOrder(1) }
+        );
+
+        let names: Vec<&str> = books.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, vec!["AAPL", "MSFT", "TSLA"]);
+        assert_eq!(books[0].1.best_ask(), Some(Price::new(100)));
+        assert_eq!(books[1].1.order_count(), 0);
+        assert_eq!(books[2].1.best_ask(), None);
+    }
+
+    #[test]
+    fn forwards_order_events_after_each_request() {
+        let books = vec![("AAPL".to_string(), OrderBook::new().with_order_events())];
+        let engine = ShardedEngine::start(books, EngineConfig::default());
+        let aapl = engine.symbol_id("AAPL").unwrap();
+        engine.submit(aapl, Command::Add(Order::new(1, Side::Sell, 100, 5))).unwrap();
+        engine.submit(aapl, Command::Add(Order::new(2, Side::Buy, 100, 3))).unwrap();
+
+        let (_, events) = engine.shutdown();
+        let feed: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ExecutionEvent::Order { event, .. } => Some(event.kind),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            feed,
+            vec![
+                OrderEventKind::Added(RestingOrder {
+                    order_id: 1,
+                    side: Side::Sell,
+                    price: Price::new(100),
+                    quantity: Qty::new(5)
+                }),
+                OrderEventKind::Executed {
+                    order_id: 1,
+                    trade_id: 1,
+                    price: Price::new(100),
+                    quantity: Qty::new(3),
+                    remaining: Qty::new(2)
+                },
+            ]
+        );
+        assert!(matches!(events.last(), Some(ExecutionEvent::Order { .. })));
+    }
+
+    #[test]
+    fn ticks_expire_orders_and_apply_scheduled_transitions() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut book = OrderBook::with_clock(clock.clone());
+        book.schedule_session_state(2_000, SessionState::Closed);
+        book.add_order(Order::new(1, Side::Buy, 100, 5).with_time_in_force(TimeInForce::Gtd(1_500))).unwrap();
+        let engine = ShardedEngine::start(vec![("AAPL".to_string(), book)], EngineConfig {
+            tick_interval: None,
+            ..EngineConfig::default()
+        });
+        let aapl = engine.symbol_id("AAPL").unwrap();
+        clock.set(2_000);
+        engine.submit(aapl, Command::Tick).unwrap();
+
+        let (books, events) = engine.shutdown();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ExecutionEvent::Cancelled { event, .. } if event.order_id == 1 && event.reason == CancelReason::Expired
+        )));
+        assert_eq!(books[0].1.session_state(), SessionState::Closed);
+    }
+
+    #[test]
+    fn unknown_symbols_are_refused_without_touching_the_shards() {
+        let engine = engine(2, None);
+        let order = Command::Add(Order::new(1, Side::Buy, 100, 1));
+        assert_eq!(engine.submit(SymbolId(3), order), Err(SubmitError::UnknownSymbol(SymbolId(3))));
+        let (books, events) = engine.shutdown();
+        assert!(events.is_empty());
+        assert_eq!(books.len(), 3);
+    }
+
+    #[test]
+    fn concurrent_producers_reach_every_shard() {
+        let engine = engine(3, None);
+        let symbols: Vec<SymbolId> = ["AAPL", "MSFT", "TSLA"]
+            .iter()
+            .map(|symbol| engine.symbol_id(symbol).unwrap())
+            .collect();
+        std::thread::scope(|scope| {
+            for producer in 0..4u64 {
+                let engine = &engine;
+                let symbols = &symbols;
+                scope.spawn(move || {
+                    for i in 0..100u64 {
+                        let order = Order::new(producer * 1_000 + i, Side::Buy, 100, 1);
+                        let mut command = Command::Add(order);
+                        while let Err(SubmitError::QueueFull(rejected)) =
+                            engine.submit(symbols[i as usize % 3], command)
+                        {
+                            command = rejected.command;
+                        }
+                    }
+                });
+            }
+        });
+        let (books, events) = engine.shutdown();
+        assert_eq!(events.len(), 400);
+        assert_eq!(books.iter().map(|(_, book)| book.order_count()).sum::<usize>(), 400);
+    }
+}
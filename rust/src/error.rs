@@ -0,0 +1,121 @@
+//! Reject reasons for order book operations.
+use std::fmt;
+
+use crate::order::{Side, TrailingOffset};
+use crate::price_band::BandWidth;
+use crate::session::{Operation, SessionState};
+use crate::units::{Price, Qty};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookError {
+    UnknownOrder(u64),
+    DuplicateOrderId(u64),
+    InvalidQuantity(Qty),
+    InvalidPrice(Price),
+    SideMismatch { order_id: u64, expected: Side, actual: Side },
+    PriceMismatch { order_id: u64, expected: Price, actual: Price },
+    PostOnlyWouldCross(u64),
+    ReduceOnlyWouldIncrease(u64),
+    /// The order combines instructions that cannot be honoured together,
+    /// such as a post-only market or IOC order.
+    IncompatibleInstructions(u64),
+    /// A price derived from market data (e.g. a trailing stop's initial
+    /// stop price) was requested before the book had one.
+    NoReferencePrice(u64),
+    /// A good-till-date order whose expiry is not in the future.
+    AlreadyExpired(u64),
+    /// Only plain resting limit orders can join a call auction.
+    NotAcceptedInAuction(u64),
+    /// The current session state does not allow the operation.
+    SessionRejected { state: SessionState, operation: Operation },
+    InvalidTransition { from: SessionState, to: SessionState },
+    /// A limit price outside the static price band.
+    OutsidePriceBand { order_id: u64, price: Price, low: Price, high: Price },
+    PriceNotOnTick { order_id: u64, price: Price, tick_size: Price },
+    QuantityNotOnLot { order_id: u64, quantity: Qty, lot_size: Qty },
+    QuantityOutOfRange { order_id: u64, quantity: Qty, min: Qty, max: Qty },
+    NotionalTooLarge { order_id: u64, notional: u64, max: u64 },
+    /// A peg offset too large to price in half-ticks.
+    PegOffsetOutOfRange { order_id: u64, offset: i64 },
+    /// An instrument with a non-positive tick or zero lot size, or a
+    /// minimum quantity above its maximum.
+    InvalidInstrument,
+    /// A price band too wide to measure at the book's tick size.
+    PriceBandTooWide(BandWidth),
+    /// A trailing amount larger than any price distance.
+    TrailingOffsetOutOfRange { order_id: u64, offset: TrailingOffset },
+}
+
+impl fmt::Display for BookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BookError::UnknownOrder(id) => write!(f, "unknown order {}", id),
+            BookError::DuplicateOrderId(id) => write!(f, "duplicate order id {}", id),
+            BookError::InvalidQuantity(quantity) => write!(f, "invalid quantity {}", quantity),
+            BookError::InvalidPrice(price) => write!(f, "invalid price {}", price),
+            BookError::SideMismatch { order_id, expected, actual } => write!(
+                f,
+                "order {} rests on {:?} side, not {:?}",
+                order_id, actual, expected
+            ),
+            BookError::PriceMismatch { order_id, expected, actual } => write!(
+                f,
+                "order {} rests at price {}, not {}",
+                order_id, actual, expected
+            ),
+            BookError::PostOnlyWouldCross(id) => write!(f, "post-only order {} would take liquidity", id),
+            BookError::ReduceOnlyWouldIncrease(id) => {
+                write!(f, "reduce-only order {} would increase the position", id)
+            }
+            BookError::IncompatibleInstructions(id) => {
+                write!(f, "order {} has incompatible instructions", id)
+            }
+            BookError::NoReferencePrice(id) => write!(f, "no reference price for order {}", id),
+            BookError::AlreadyExpired(id) => write!(f, "order {} expires in the past", id),
+            BookError::NotAcceptedInAuction(id) => {
+                write!(f, "order {} cannot be entered during an auction", id)
+            }
+            BookError::SessionRejected { state, operation } => {
+                write!(f, "cannot {} while the session is {}", operation, state)
+            }
+            BookError::InvalidTransition { from, to } => {
+                write!(f, "session cannot move from {} to {}", from, to)
+            }
+            BookError::OutsidePriceBand { order_id, price, low, high } => write!(
+                f,
+                "order {} price {} is outside the band {}..={}",
+                order_id, price, low, high
+            ),
+            BookError::PriceNotOnTick { order_id, price, tick_size } => write!(
+                f,
+                "order {} price {} is not a multiple of the tick size {}",
+                order_id, price, tick_size
+            ),
+            BookError::QuantityNotOnLot { order_id, quantity, lot_size } => write!(
+                f,
+                "order {} quantity {} is not a multiple of the lot size {}",
+                order_id, quantity, lot_size
+            ),
+            BookError::QuantityOutOfRange { order_id, quantity, min, max } => write!(
+                f,
+                "order {} quantity {} is outside {}..={}",
+                order_id, quantity, min, max
+            ),
+            BookError::NotionalTooLarge { order_id, notional, max } => write!(
+                f,
+                "order {} notional {} exceeds the maximum {}",
+                order_id, notional, max
+            ),
+            BookError::PegOffsetOutOfRange { order_id, offset } => {
+                write!(f, "order {} peg offset {} is out of range", order_id, offset)
+            }
+            BookError::InvalidInstrument => write!(f, "invalid instrument"),
+            BookError::PriceBandTooWide(width) => write!(f, "price band {:?} is too wide", width),
+            BookError::TrailingOffsetOutOfRange { order_id, offset } => {
+                write!(f, "order {} trailing offset {:?} is out of range", order_id, offset)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
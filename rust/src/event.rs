@@ -0,0 +1,28 @@
+//! Audit events emitted by the book alongside trades.
+use crate::units::Qty;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CancelReason {
+    /// Cancelled through `cancel_order`/`cancel_order_by_id`.
+    Requested,
+    /// A day or good-till-date order reached its expiry.
+    Expired,
+    /// Removed or decremented by self-trade prevention.
+    SelfTradePrevention,
+    /// The part of an IOC, FOK or market order that could not execute.
+    Unfilled,
+    /// The rest of an order whose next trade tripped a volatility halt.
+    VolatilityInterruption,
+}
+
+/// Quantity taken off an order without trading. `quantity` is the amount
+/// cancelled, which is less than the order's size for partial cancels such
+/// as decrement-and-cancel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CancelEvent {
+    pub order_id: u64,
+    pub account_id: u64,
+    pub quantity: Qty,
+    pub reason: CancelReason,
+    pub timestamp: u64,
+}
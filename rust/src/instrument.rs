@@ -0,0 +1,256 @@
+//! Instrument reference data and the order checks it implies.
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::BookError;
+use crate::order::{Order, OrderType};
+use crate::units::{Decimal, Price, Qty};
+
+/// Trading rules for one symbol. Prices and quantities are in the same raw
+/// integer units as `Order`; `price_scale` is the number of implied decimal
+/// places in a price.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instrument {
+    pub symbol: String,
+    pub tick_size: Price,
+    pub lot_size: Qty,
+    pub min_quantity: Qty,
+    #[serde(default)]
+    pub max_quantity: Option<Qty>,
+    /// Largest allowed `|price| * quantity`.
+    #[serde(default)]
+    pub max_notional: Option<u64>,
+    #[serde(default)]
+    pub price_scale: u32,
+    /// Accepts zero and negative prices, as for spreads and commodities
+    /// that can trade below zero. Otherwise prices must be positive.
+    #[serde(default)]
+    pub allow_negative_prices: bool,
+}
+
+impl Instrument {
+    /// An instrument with unit tick and lot and no quantity limits.
+    pub fn new(symbol: &str) -> Self {
+        Instrument {
+            symbol: symbol.to_string(),
+            tick_size: Price::new(1),
+            lot_size: Qty::new(1),
+            min_quantity: Qty::new(1),
+            max_quantity: None,
+            max_notional: None,
+            price_scale: 0,
+            allow_negative_prices: false,
+        }
+    }
+
+    /// `price` as a decimal with `price_scale` places, e.g. for display.
+    pub fn price_to_decimal(&self, price: Price) -> Decimal {
+        price.to_decimal(self.price_scale)
+    }
+
+    /// Parses a decimal price such as `"101.25"` or `"-3.5"`, or `None` if
+    /// it is malformed, out of range or finer than `price_scale`.
+    pub fn parse_price(&self, text: &str) -> Option<Price> {
+        Price::from_decimal(text.parse().ok()?, self.price_scale)
+    }
+
+    /// Checks a new order's prices, size and notional, valuing it at
+    /// `price` as for `validate_quantity`.
+    pub fn validate_order(&self, order: &Order, price: Option<Price>) -> Result<(), BookError> {
+        let triggered = order.into_triggered();
+        if triggered.order_type == OrderType::Limit && order.peg.is_none() {
+            self.check_tick(order.order_id, order.price)?;
+        }
+        if order.is_stop() && order.trailing_offset.is_none() {
+            self.check_tick(order.order_id, order.stop_price)?;
+        }
+        if let Some(peak) = order.display_quantity {
+            self.check_lot(order.order_id, peak)?;
+        }
+        self.validate_quantity(order, order.remaining(), price)
+    }
+
+    /// Checks `quantity` as the size of `order`, valued at `price` for the
+    /// notional limit. Without a price, as for a market order facing an
+    /// empty book, a notional limit cannot be checked and the order is
+    /// rejected with `NoReferencePrice`.
+    pub fn validate_quantity(&self, order: &Order, quantity: Qty, price: Option<Price>) -> Result<(), BookError> {
+        let order_id = order.order_id;
+        self.check_lot(order_id, quantity)?;
+        let max = self.max_quantity.unwrap_or(Qty::MAX);
+        if quantity < self.min_quantity || quantity > max {
+            return Err(BookError::QuantityOutOfRange { order_id, quantity, min: self.min_quantity, max });
+        }
+        if let Some(max) = self.max_notional {
+            let price = price.ok_or(BookError::NoReferencePrice(order_id))?;
+            let notional = price.raw().unsigned_abs().saturating_mul(quantity.raw());
+            if notional > max {
+                return Err(BookError::NotionalTooLarge { order_id, notional, max });
+            }
+        }
+        Ok(())
+    }
+
+    fn check_tick(&self, order_id: u64, price: Price) -> Result<(), BookError> {
+        if price.raw().rem_euclid(self.tick_size.raw()) != 0 {
+            return Err(BookError::PriceNotOnTick { order_id, price, tick_size: self.tick_size });
+        }
+        Ok(())
+    }
+
+    fn check_lot(&self, order_id: u64, quantity: Qty) -> Result<(), BookError> {
+        if !quantity.raw().is_multiple_of(self.lot_size.raw()) {
+            return Err(BookError::QuantityNotOnLot { order_id, quantity, lot_size: self.lot_size });
+        }
+        Ok(())
+    }
+
+    /// Whether the tick and lot sizes are positive and the minimum
+    /// quantity is within the maximum.
+    pub fn is_valid(&self) -> bool {
+        let max = self.max_quantity.unwrap_or(Qty::MAX);
+        self.tick_size > Price::ZERO && !self.lot_size.is_zero() && self.min_quantity <= max
+    }
+
+    fn check(&self) -> Result<(), ReferenceDataError> {
+        if !self.is_valid() {
+            return Err(ReferenceDataError::Invalid(self.symbol.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// A reference-data file: `[[instruments]]` tables in TOML, or an
+/// `"instruments"` array in JSON.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReferenceData {
+    pub instruments: Vec<Instrument>,
+}
+
+#[derive(Debug)]
+pub enum ReferenceDataError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Toml(toml::de::Error),
+    /// The file extension is neither `.toml` nor `.json`.
+    UnknownFormat(String),
+    /// An instrument with a non-positive tick or zero lot size, or a
+    /// minimum quantity above its maximum.
+    Invalid(String),
+}
+
+impl ReferenceData {
+    pub fn from_toml_str(text: &str) -> Result<Self, ReferenceDataError> {
+        let data: ReferenceData = toml::from_str(text).map_err(ReferenceDataError::Toml)?;
+        data.check()
+    }
+
+    pub fn from_json_str(text: &str) -> Result<Self, ReferenceDataError> {
+        let data: ReferenceData = serde_json::from_str(text).map_err(ReferenceDataError::Json)?;
+        data.check()
+    }
+
+    /// Reads a `.toml` or `.json` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReferenceDataError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path).map_err(ReferenceDataError::Io)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml_str(&text),
+            Some("json") => Self::from_json_str(&text),
+            _ => Err(ReferenceDataError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&Instrument> {
+        self.instruments.iter().find(|instrument| instrument.symbol == symbol)
+    }
+
+    fn check(self) -> Result<Self, ReferenceDataError> {
+        for instrument in &self.instruments {
+            instrument.check()?;
+        }
+        Ok(self)
+    }
+}
+
+impl fmt::Display for ReferenceDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReferenceDataError::Io(err) => write!(f, "cannot read reference data: {}", err),
+            ReferenceDataError::Json(err) => write!(f, "invalid JSON reference data: {}", err),
+            ReferenceDataError::Toml(err) => write!(f, "invalid TOML reference data: {}", err),
+            ReferenceDataError::UnknownFormat(path) => {
+                write!(f, "{} is not a .toml or .json file", path)
+            }
+            ReferenceDataError::Invalid(symbol) => write!(f, "instrument {} has invalid limits", symbol),
+        }
+    }
+}
+
+impl std::error::Error for ReferenceDataError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Instrument, ReferenceData, ReferenceDataError};
+    use crate::units::{Price, Qty};
+
+    const TOML: &str = r#"
+        [[instruments]]
+        symbol = "ESZ4"
+        tick_size = 25
+        lot_size = 1
+        min_quantity = 1
+        max_quantity = 500
+        price_scale = 2
+
+        [[instruments]]
+        symbol = "AAPL"
+        tick_size = 1
+        lot_size = 100
+        min_quantity = 100
+        max_notional = 10000000
+    "#;
+
+    #[test]
+    fn toml_and_json_load_the_same_instruments() {
+        let data = ReferenceData::from_toml_str(TOML).unwrap();
+        let json = serde_json::to_string(&data).unwrap();
+        assert_eq!(ReferenceData::from_json_str(&json).unwrap(), data);
+
+        let es = data.get("ESZ4").unwrap();
+        assert_eq!((es.tick_size, es.max_quantity, es.price_scale), (Price::new(25), Some(Qty::new(500)), 2));
+        assert_eq!(es.price_to_decimal(Price::new(452_575)).to_string(), "4525.75");
+        assert_eq!(es.parse_price("4525.75"), Some(Price::new(452_575)));
+        assert_eq!(es.parse_price("4525.755"), None);
+        let aapl = data.get("AAPL").unwrap();
+        assert_eq!((aapl.max_quantity, aapl.max_notional), (None, Some(10_000_000)));
+    }
+
+    #[test]
+    fn rejects_unusable_instruments() {
+        let mut instrument = Instrument::new("BAD");
+        instrument.tick_size = Price::ZERO;
+        let json = serde_json::to_string(&ReferenceData { instruments: vec![instrument] }).unwrap();
+        assert!(matches!(
+            ReferenceData::from_json_str(&json),
+            Err(ReferenceDataError::Invalid(symbol)) if symbol == "BAD"
+        ));
+    }
+
+    #[test]
+    fn load_picks_the_format_from_the_extension() {
+        let dir = std::env::temp_dir().join(format!("reference-data-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("instruments.toml");
+        std::fs::write(&toml_path, TOML).unwrap();
+        let yaml_path = dir.join("instruments.yaml");
+        std::fs::write(&yaml_path, TOML).unwrap();
+
+        assert_eq!(ReferenceData::load(&toml_path).unwrap().instruments.len(), 2);
+        assert!(matches!(ReferenceData::load(&yaml_path), Err(ReferenceDataError::UnknownFormat(_))));
+        assert!(matches!(ReferenceData::load(dir.join("missing.json")), Err(ReferenceDataError::Io(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,5 +1,20 @@
+pub mod allocation;
+pub mod auction;
+pub mod book_manager;
+pub mod clock;
+pub mod engine;
+pub mod error;
+pub mod event;
+pub mod instrument;
+pub mod market_data;
 pub mod order;
 pub mod order_book;
+pub mod peg_book;
+pub mod price_band;
+pub mod price_level;
+pub mod session;
+pub mod stop_book;
 pub mod trade;
+pub mod units;
 pub mod concurrent_queue;
 pub mod market_simulator;
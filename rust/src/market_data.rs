@@ -0,0 +1,278 @@
+//! Read-only views of a book for market data consumers.
+//!
+//! Besides point-in-time depth, a book can publish incremental L2
+//! updates: every change to a level's displayed quantity or order count
+//! becomes one `LevelUpdate` with the next sequence number. A consumer
+//! starts from a `DepthSnapshot`, applies the updates that follow it and
+//! resynchronises from a fresh snapshot when `DepthReplica::apply`
+//! reports a gap.
+//!
+//! The order-by-order (L3) feed works the same way with `OrderEvent`s,
+//! in the style of ITCH: each change to a resting order on the price
+//! levels, numbered in its own sequence and replayed on top of an
+//! `OrderSnapshot`. Parked stops and pegged orders are not on the levels
+//! and are not reported.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::order::Side;
+use crate::units::{Price, Qty};
+
+/// One aggregated price level: displayed quantity and resting orders.
+/// Iceberg reserves are not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthLevel {
+    pub price: Price,
+    pub quantity: Qty,
+    pub order_count: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LevelAction {
+    /// A price level appeared.
+    Add,
+    /// An existing level's quantity or order count changed.
+    Update,
+    /// A level emptied; quantity and order count are zero.
+    Delete,
+}
+
+/// The new state of one price level. Sequence numbers start at 1 and
+/// increase by one per update.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LevelUpdate {
+    pub sequence: u64,
+    pub side: Side,
+    pub action: LevelAction,
+    pub price: Price,
+    pub quantity: Qty,
+    pub order_count: usize,
+}
+
+/// Every level of both sides, best first, as of update `sequence`: the
+/// next update to apply is `sequence + 1`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthSnapshot {
+    pub sequence: u64,
+    pub bids: Vec<DepthLevel>,
+    pub asks: Vec<DepthLevel>,
+}
+
+/// A resting order as the L3 feed shows it: `quantity` is displayed
+/// quantity only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RestingOrder {
+    pub order_id: u64,
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Qty,
+}
+
+/// What happened to one resting order. `remaining` is what the order
+/// displays afterwards; an order left with nothing displayed is off the
+/// book unless a `Replaced` for the same id follows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum OrderEventKind {
+    /// The order joined the back of its price level.
+    Added(RestingOrder),
+    /// The order traded `quantity` at `price` in trade `trade_id`. In an
+    /// auction uncross this can include iceberg reserve.
+    Executed { order_id: u64, trade_id: u64, price: Price, quantity: Qty, remaining: Qty },
+    /// `quantity` was cancelled without the order losing its place.
+    Reduced { order_id: u64, quantity: Qty, remaining: Qty },
+    /// The order now displays `quantity` at `price` in place of
+    /// `old_quantity`. An iceberg whose displayed quantity is refreshed
+    /// goes to the back of its level; an amend that raises the quantity
+    /// keeps its place, as `keeps_priority` says.
+    Replaced { order_id: u64, price: Price, old_quantity: Qty, quantity: Qty, keeps_priority: bool },
+    /// The order was cancelled, or expired, in full.
+    Deleted { order_id: u64 },
+}
+
+/// One L3 event. Sequence numbers start at 1 and increase by one per event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderEvent {
+    pub sequence: u64,
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub kind: OrderEventKind,
+}
+
+/// Every resting order in priority order, bids best first and then asks
+/// best first, as of order event `sequence`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OrderSnapshot {
+    pub sequence: u64,
+    pub orders: Vec<RestingOrder>,
+}
+
+/// An update arrived out of order: at least one before it was missed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceGap {
+    pub expected: u64,
+    pub received: u64,
+}
+
+/// Level changes the book has made since they were last turned into
+/// updates, keyed by price with each level's state before its first change.
+#[derive(Debug, Default)]
+pub struct LevelChanges {
+    sequence: u64,
+    bids: BTreeMap<Price, Option<DepthLevel>>,
+    asks: BTreeMap<Price, Option<DepthLevel>>,
+    pending: Vec<LevelUpdate>,
+}
+
+impl LevelChanges {
+    /// Sequence number of the last update issued.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Notes that the level at `price` is about to change from `before`.
+    /// Only the first call between flushes counts.
+    pub fn touch(&mut self, side: Side, price: Price, before: Option<DepthLevel>) {
+        let changed = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        changed.entry(price).or_insert(before);
+    }
+
+    /// Issues an update for every touched level whose state differs from
+    /// before, given each level's current state, bids then asks in price
+    /// order. Levels that changed and changed back are skipped.
+    pub fn flush(&mut self, current: impl Fn(Side, Price) -> Option<DepthLevel>) {
+        for side in [Side::Buy, Side::Sell] {
+            let changed = match side {
+                Side::Buy => std::mem::take(&mut self.bids),
+                Side::Sell => std::mem::take(&mut self.asks),
+            };
+            for (price, before) in changed {
+                let after = current(side, price);
+                if before == after {
+                    continue;
+                }
+                let (action, quantity, order_count) = match (before, after) {
+                    (_, None) => (LevelAction::Delete, Qty::ZERO, 0),
+                    (None, Some(level)) => (LevelAction::Add, level.quantity, level.order_count),
+                    (Some(_), Some(level)) => (LevelAction::Update, level.quantity, level.order_count),
+                };
+                self.sequence += 1;
+                self.pending.push(LevelUpdate { sequence: self.sequence, side, action, price, quantity, order_count });
+            }
+        }
+    }
+
+    /// Flushed updates not yet handed out, oldest first.
+    pub fn take(&mut self) -> Vec<LevelUpdate> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// A consumer-side copy of the book's levels, kept current by applying
+/// updates in sequence.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepthReplica {
+    sequence: u64,
+    bids: BTreeMap<Price, DepthLevel>,
+    asks: BTreeMap<Price, DepthLevel>,
+}
+
+impl DepthReplica {
+    pub fn new(snapshot: &DepthSnapshot) -> Self {
+        let levels = |levels: &[DepthLevel]| levels.iter().map(|level| (level.price, *level)).collect();
+        DepthReplica { sequence: snapshot.sequence, bids: levels(&snapshot.bids), asks: levels(&snapshot.asks) }
+    }
+
+    /// Sequence number of the last update applied.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Applies the next update. Updates the replica already covers, such
+    /// as ones sent before its snapshot, are ignored; a later one than the
+    /// next leaves the replica unchanged and reports the gap.
+    pub fn apply(&mut self, update: &LevelUpdate) -> Result<(), SequenceGap> {
+        if update.sequence <= self.sequence {
+            return Ok(());
+        }
+        if update.sequence != self.sequence + 1 {
+            return Err(SequenceGap { expected: self.sequence + 1, received: update.sequence });
+        }
+        self.sequence = update.sequence;
+        let levels = match update.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        match update.action {
+            LevelAction::Delete => {
+                levels.remove(&update.price);
+            }
+            LevelAction::Add | LevelAction::Update => {
+                let (price, quantity, order_count) = (update.price, update.quantity, update.order_count);
+                levels.insert(price, DepthLevel { price, quantity, order_count });
+            }
+        }
+        Ok(())
+    }
+
+    /// The best `levels` price levels on `side`, best first.
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<DepthLevel> {
+        match side {
+            Side::Buy => self.bids.values().rev().take(levels).copied().collect(),
+            Side::Sell => self.asks.values().take(levels).copied().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DepthLevel, DepthReplica, DepthSnapshot, LevelAction, LevelChanges, SequenceGap};
+    use crate::order::Side;
+    use crate::units::{Price, Qty};
+
+    fn level(price: i64, quantity: u64, order_count: usize) -> DepthLevel {
+        DepthLevel { price: Price::new(price), quantity: Qty::new(quantity), order_count }
+    }
+
+    #[test]
+    fn replica_follows_flushed_changes_and_detects_gaps() {
+        let snapshot = DepthSnapshot { sequence: 0, bids: vec![level(99, 5, 1)], asks: vec![level(101, 4, 1)] };
+        let mut replica = DepthReplica::new(&snapshot);
+        let mut changes = LevelChanges::default();
+
+        changes.touch(Side::Buy, Price::new(99), Some(level(99, 5, 1)));
+        changes.touch(Side::Buy, Price::new(99), Some(level(99, 8, 2)));
+        changes.touch(Side::Buy, Price::new(100), None);
+        changes.touch(Side::Sell, Price::new(101), Some(level(101, 4, 1)));
+        changes.touch(Side::Sell, Price::new(102), None);
+        changes.flush(|side, price| match (side, price.raw()) {
+            (Side::Buy, 99) => Some(level(99, 8, 2)),
+            (Side::Buy, 100) => Some(level(100, 1, 1)),
+            _ => None,
+        });
+        let updates = changes.take();
+        let actions: Vec<_> = updates.iter().map(|u| (u.sequence, u.side, u.action, u.price.raw())).collect();
+        assert_eq!(
+            actions,
+            vec![
+                (1, Side::Buy, LevelAction::Update, 99),
+                (2, Side::Buy, LevelAction::Add, 100),
+                (3, Side::Sell, LevelAction::Delete, 101),
+            ]
+        );
+
+        assert_eq!(replica.apply(&updates[0]), Ok(()));
+        assert_eq!(replica.apply(&updates[2]), Err(SequenceGap { expected: 2, received: 3 }));
+        assert_eq!(replica.sequence(), 1);
+        for update in &updates {
+            replica.apply(update).unwrap();
+        }
+        assert_eq!(replica.depth(Side::Buy, 10), vec![level(100, 1, 1), level(99, 8, 2)]);
+        assert_eq!(replica.depth(Side::Sell, 10), vec![]);
+        assert!(changes.take().is_empty());
+    }
+}
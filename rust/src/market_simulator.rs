@@ -1,31 +1,97 @@
+use crate::clock::{Clock, SystemClock, NANOS_PER_MILLI};
+use crate::market_data::{DepthLevel, LevelAction, LevelUpdate, OrderEvent, OrderEventKind, RestingOrder};
 use crate::order::{Order, Side};
 use crate::order_book::OrderBook;
 use crate::trade::Trade;
+use crate::units::{Decimal, Price, Qty};
 use rand::prelude::*;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Decimal places in simulated prices: they are quoted in cents.
+pub const PRICE_SCALE: u32 = 2;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceLevelData {
-    pub price: f64,
-    pub quantity: u64,
+    pub price: Decimal,
+    pub quantity: Qty,
     pub order_count: usize,
 }
 
+/// Every level of the book as of update `sequence`, and every resting
+/// order as of order event `order_sequence`, for a client to start from
+/// before applying `OrderBookDelta`s.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderBookSnapshot {
+    pub sequence: u64,
     pub bids: Vec<PriceLevelData>,
     pub asks: Vec<PriceLevelData>,
+    pub order_sequence: u64,
+    pub orders: Vec<OrderData>,
     pub trades: Vec<TradeData>,
     pub metrics: MetricsData,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LevelUpdateData {
+    pub sequence: u64,
+    pub side: Side,
+    pub action: LevelAction,
+    pub price: Decimal,
+    pub quantity: Qty,
+    pub order_count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderData {
+    pub order_id: u64,
+    pub side: Side,
+    pub price: Decimal,
+    pub quantity: Qty,
+}
+
+/// An `OrderEvent` with decimal prices and a millisecond timestamp.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderEventData {
+    pub sequence: u64,
+    pub timestamp: u64,
+    #[serde(flatten)]
+    pub kind: OrderEventDataKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum OrderEventDataKind {
+    Added(OrderData),
+    Executed { order_id: u64, trade_id: u64, price: Decimal, quantity: Qty, remaining: Qty },
+    Reduced { order_id: u64, quantity: Qty, remaining: Qty },
+    Replaced { order_id: u64, price: Decimal, old_quantity: Qty, quantity: Qty, keeps_priority: bool },
+    Deleted { order_id: u64 },
+}
+
+/// What changed in one simulation step: level updates and order events
+/// in sequence order and the trades since the previous step, oldest first.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderBookDelta {
+    pub updates: Vec<LevelUpdateData>,
+    pub order_events: Vec<OrderEventData>,
+    pub trades: Vec<TradeData>,
+    pub metrics: MetricsData,
+}
+
+impl OrderBookDelta {
+    /// Nothing traded and no order or level changed.
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty() && self.order_events.is_empty() && self.trades.is_empty()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TradeData {
     pub id: u64,
-    pub price: f64,
-    pub quantity: u64,
+    pub price: Decimal,
+    pub quantity: Qty,
     pub timestamp: u64,
     #[serde(rename = "buyOrderId")]
     pub buy_order_id: u64,
@@ -39,32 +105,57 @@ pub struct MetricsData {
     pub total_orders: u64,
     #[serde(rename = "totalTrades")]
     pub total_trades: u64,
-    pub volume: u64,
+    pub volume: Qty,
     #[serde(rename = "lastPrice")]
-    pub last_price: f64,
+    pub last_price: Decimal,
 }
 
+/// Trades kept for snapshots and the trade tape.
+const RECENT_TRADES: usize = 100;
+/// Orders tracked for cancellation; the oldest is cancelled once more
+/// are submitted, so the book stays near the current price.
+const MAX_LIVE_ORDERS: usize = 200;
+
+/// Generates random order flow around a drifting price and runs it
+/// through a real `OrderBook`; snapshots and deltas show the resulting
+/// levels and trades.
 pub struct MarketSimulator {
     order_book: OrderBook,
     order_id_counter: u64,
-    current_price: f64,
-    recent_trades: Vec<TradeData>,
+    /// Submitted order ids, oldest first.
+    submitted: VecDeque<u64>,
+    current_price: Price,
+    recent_trades: VecDeque<TradeData>,
+    /// Trades not yet published in a delta.
+    new_trades: Vec<TradeData>,
     metrics: MetricsData,
     rng: StdRng,
 }
 
+impl Default for MarketSimulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl MarketSimulator {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         let mut simulator = MarketSimulator {
-            order_book: OrderBook::new(),
+            order_book: OrderBook::with_clock(clock).with_level_updates().with_order_events(),
             order_id_counter: 1,
-            current_price: 100.0,
-            recent_trades: Vec::new(),
+            submitted: VecDeque::new(),
+            current_price: Price::new(10_000),
+            recent_trades: VecDeque::new(),
+            new_trades: Vec::new(),
             metrics: MetricsData {
                 total_orders: 0,
                 total_trades: 0,
-                volume: 0,
-                last_price: 100.0,
+                volume: Qty::ZERO,
+                last_price: Price::new(10_000).to_decimal(PRICE_SCALE),
             },
             rng: StdRng::seed_from_u64(42),
         };
@@ -74,114 +165,290 @@ impl MarketSimulator {
         simulator
     }
 
+    pub fn order_book(&self) -> &OrderBook {
+        &self.order_book
+    }
+
     fn initialize_market(&mut self) {
         // Add initial orders around the current price
         for _ in 0..50 {
             let order = self.generate_order();
-            self.add_order_to_book(order);
+            self.submit(order);
         }
     }
 
     fn generate_order(&mut self) -> Order {
         let side = if self.rng.gen::<bool>() { Side::Buy } else { Side::Sell };
-        let spread = 0.5;
-        let price_variation = (self.rng.gen::<f64>() - 0.5) * 2.0; // -1 to 1
-        
-        let price = match side {
-            Side::Buy => self.current_price - spread / 2.0 + price_variation,
-            Side::Sell => self.current_price + spread / 2.0 + price_variation,
+        let half_spread = 25;
+        let price_variation = self.rng.gen_range(-100..=100); // up to a dollar either way
+
+        let offset = match side {
+            Side::Buy => price_variation - half_spread,
+            Side::Sell => price_variation + half_spread,
         };
-        
-        let price_cents = (price * 100.0).round() as u64;
+        let price = offset_price(self.current_price, offset);
         let quantity = self.rng.gen_range(10..=100);
-        
-        Order::new(self.order_id_counter, side, price_cents, quantity)
+
+        Order::new(self.order_id_counter, side, price.raw(), quantity)
     }
 
-    fn add_order_to_book(&mut self, mut order: Order) {
+    /// A market order that takes liquidity from a random side.
+    fn generate_market_order(&mut self) -> Order {
+        let side = if self.rng.gen::<bool>() { Side::Buy } else { Side::Sell };
+        let quantity = self.rng.gen_range(10..=50);
+        Order::market(self.order_id_counter, side, quantity)
+    }
+
+    /// Runs `order` through the book, records its trades and cancels the
+    /// oldest order if too many are tracked.
+    fn submit(&mut self, order: Order) {
         self.order_id_counter += 1;
+        let Ok(trades) = self.order_book.add_order(order) else { return };
         self.metrics.total_orders += 1;
-        
-        // For simulation, we'll add the order directly
-        // In a real system, this would go through the matching engine
-        self.order_book.add_order(order);
+        for trade in &trades {
+            self.record_trade(order.side, trade);
+        }
+
+        self.submitted.push_back(order.order_id);
+        if self.submitted.len() > MAX_LIVE_ORDERS {
+            let oldest = self.submitted.pop_front().expect("tracked orders");
+            // Already gone if it was filled.
+            let _ = self.order_book.cancel_order_by_id(oldest);
+        }
+        self.order_book.drain_cancels();
+    }
+
+    fn record_trade(&mut self, taker_side: Side, trade: &Trade) {
+        let (buy_order_id, sell_order_id) = match taker_side {
+            Side::Buy => (trade.taker_order_id, trade.maker_order_id),
+            Side::Sell => (trade.maker_order_id, trade.taker_order_id),
+        };
+        let price = trade.price.to_decimal(PRICE_SCALE);
+        let data = TradeData {
+            id: trade.trade_id,
+            price,
+            quantity: trade.quantity,
+            timestamp: trade.timestamp / NANOS_PER_MILLI,
+            buy_order_id,
+            sell_order_id,
+        };
+        self.new_trades.push(data.clone());
+        self.recent_trades.push_back(data);
+        if self.recent_trades.len() > RECENT_TRADES {
+            self.recent_trades.pop_front();
+        }
+
+        self.metrics.total_trades += 1;
+        self.metrics.volume = self.metrics.volume.saturating_add(trade.quantity);
+        self.metrics.last_price = price;
+        self.current_price = trade.price;
     }
 
-    pub fn simulate_market_activity(&mut self) -> OrderBookSnapshot {
+    /// Runs one step of order flow and returns what it changed.
+    pub fn simulate_market_activity(&mut self) -> OrderBookDelta {
         // Add some new orders
         for _ in 0..self.rng.gen_range(1..=5) {
             let order = self.generate_order();
-            self.add_order_to_book(order);
+            self.submit(order);
         }
 
-        // Occasionally generate a trade by crossing the spread
+        // Occasionally cross the spread with a market order
         if self.rng.gen::<f64>() < 0.3 {
-            self.generate_trade();
+            let order = self.generate_market_order();
+            self.submit(order);
         }
 
-        self.get_snapshot()
-    }
-
-    fn generate_trade(&mut self) {
-        let trade_price = self.current_price + (self.rng.gen::<f64>() - 0.5) * 0.2;
-        let trade_quantity = self.rng.gen_range(10..=50);
-        
-        let trade = TradeData {
-            id: self.order_id_counter,
-            price: trade_price,
-            quantity: trade_quantity,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64,
-            buy_order_id: self.rng.gen_range(1..self.order_id_counter),
-            sell_order_id: self.rng.gen_range(1..self.order_id_counter),
-        };
+        self.get_delta()
+    }
 
-        self.recent_trades.push(trade);
-        if self.recent_trades.len() > 100 {
-            self.recent_trades.remove(0);
-        }
+    /// Level updates, order events and trades since the last delta.
+    pub fn get_delta(&mut self) -> OrderBookDelta {
+        let update = |update: LevelUpdate| LevelUpdateData {
+            sequence: update.sequence,
+            side: update.side,
+            action: update.action,
+            price: update.price.to_decimal(PRICE_SCALE),
+            quantity: update.quantity,
+            order_count: update.order_count,
+        };
 
-        self.metrics.total_trades += 1;
-        self.metrics.volume += trade_quantity;
-        self.metrics.last_price = trade_price;
-        self.current_price = trade_price;
-    }
-
-    pub fn get_snapshot(&self) -> OrderBookSnapshot {
-        // For now, we'll generate mock order book data
-        // In a real implementation, this would extract from the actual order book
-        let mut bids = Vec::new();
-        let mut asks = Vec::new();
-
-        // Generate mock bid levels
-        for i in 0..20 {
-            let price = self.current_price - 0.5 - (i as f64 * 0.01);
-            let quantity = self.rng.gen_range(50..=500);
-            bids.push(PriceLevelData {
-                price,
-                quantity,
-                order_count: self.rng.gen_range(1..=5),
-            });
+        OrderBookDelta {
+            updates: self.order_book.drain_level_updates().into_iter().map(update).collect(),
+            order_events: self.order_book.drain_order_events().into_iter().map(order_event).collect(),
+            trades: std::mem::take(&mut self.new_trades),
+            metrics: self.metrics.clone(),
         }
+    }
 
-        // Generate mock ask levels
-        for i in 0..20 {
-            let price = self.current_price + 0.5 + (i as f64 * 0.01);
-            let quantity = self.rng.gen_range(50..=500);
-            asks.push(PriceLevelData {
-                price,
-                quantity,
-                order_count: self.rng.gen_range(1..=5),
-            });
-        }
+    /// Every level, best first on each side, every resting order in
+    /// priority order and the latest trades, newest first. Deltas taken
+    /// afterwards may repeat updates and order events up to the snapshot's
+    /// sequences; clients skip those.
+    pub fn get_snapshot(&mut self) -> OrderBookSnapshot {
+        let level = |level: DepthLevel| PriceLevelData {
+            price: level.price.to_decimal(PRICE_SCALE),
+            quantity: level.quantity,
+            order_count: level.order_count,
+        };
+        let depth = self.order_book.depth_snapshot();
+        let orders = self.order_book.order_snapshot();
 
         OrderBookSnapshot {
-            bids,
-            asks,
+            sequence: depth.sequence,
+            bids: depth.bids.into_iter().map(level).collect(),
+            asks: depth.asks.into_iter().map(level).collect(),
+            order_sequence: orders.sequence,
+            orders: orders.orders.iter().map(order_data).collect(),
             trades: self.recent_trades.iter().rev().take(10).cloned().collect(),
             metrics: self.metrics.clone(),
         }
     }
-}
\ No newline at end of file
+}
+
+fn order_data(order: &RestingOrder) -> OrderData {
+    OrderData {
+        order_id: order.order_id,
+        side: order.side,
+        price: order.price.to_decimal(PRICE_SCALE),
+        quantity: order.quantity,
+    }
+}
+
+fn order_event(event: OrderEvent) -> OrderEventData {
+    let kind = match event.kind {
+        OrderEventKind::Added(order) => OrderEventDataKind::Added(order_data(&order)),
+        OrderEventKind::Executed { order_id, trade_id, price, quantity, remaining } => {
+            let price = price.to_decimal(PRICE_SCALE);
+            OrderEventDataKind::Executed { order_id, trade_id, price, quantity, remaining }
+        }
+        OrderEventKind::Reduced { order_id, quantity, remaining } => {
+            OrderEventDataKind::Reduced { order_id, quantity, remaining }
+        }
+        OrderEventKind::Replaced { order_id, price, old_quantity, quantity, keeps_priority } => {
+            let price = price.to_decimal(PRICE_SCALE);
+            OrderEventDataKind::Replaced { order_id, price, old_quantity, quantity, keeps_priority }
+        }
+        OrderEventKind::Deleted { order_id } => OrderEventDataKind::Deleted { order_id },
+    };
+    OrderEventData { sequence: event.sequence, timestamp: event.timestamp / NANOS_PER_MILLI, kind }
+}
+
+/// `price` moved by `cents`, floored at one cent.
+fn offset_price(price: Price, cents: i64) -> Price {
+    Price::new(price.raw().saturating_add(cents).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MarketSimulator, OrderData, OrderEventDataKind, PriceLevelData, MAX_LIVE_ORDERS, PRICE_SCALE};
+    use crate::clock::ManualClock;
+    use crate::market_data::LevelAction;
+    use crate::order::Side;
+    use crate::units::Price;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn snapshots_come_from_the_real_book() {
+        let mut simulator = MarketSimulator::with_clock(Arc::new(ManualClock::new(0)));
+        for _ in 0..100 {
+            simulator.simulate_market_activity();
+        }
+        let snapshot = simulator.get_snapshot();
+        let book = simulator.order_book();
+
+        assert_eq!(snapshot.bids[0].price, book.best_bid().unwrap().to_decimal(PRICE_SCALE));
+        assert_eq!(snapshot.asks[0].price, book.best_ask().unwrap().to_decimal(PRICE_SCALE));
+        assert!(snapshot.bids.windows(2).all(|pair| pair[0].price.units() > pair[1].price.units()));
+        assert!(snapshot.asks.windows(2).all(|pair| pair[0].price.units() < pair[1].price.units()));
+        assert!(snapshot.bids[0].price.units() < snapshot.asks[0].price.units());
+        assert!(book.order_count() <= MAX_LIVE_ORDERS);
+
+        let last = &snapshot.trades[0];
+        assert_eq!(Price::from_decimal(last.price, PRICE_SCALE), book.last_trade_price());
+        assert_eq!(snapshot.metrics.last_price, last.price);
+        assert_ne!(last.buy_order_id, last.sell_order_id);
+    }
+
+    #[test]
+    fn deltas_rebuild_the_snapshot() {
+        let mut simulator = MarketSimulator::with_clock(Arc::new(ManualClock::new(0)));
+        let levels = |levels: &[PriceLevelData]| -> BTreeMap<i128, (u64, usize)> {
+            levels.iter().map(|l| (l.price.units(), (l.quantity.raw(), l.order_count))).collect()
+        };
+        let snapshot = simulator.get_snapshot();
+        let mut sequence = snapshot.sequence;
+        let (mut bids, mut asks) = (levels(&snapshot.bids), levels(&snapshot.asks));
+
+        for _ in 0..100 {
+            let delta = simulator.simulate_market_activity();
+            for update in delta.updates.iter().filter(|update| update.sequence > snapshot.sequence) {
+                assert_eq!(update.sequence, sequence + 1);
+                sequence = update.sequence;
+                let side = match update.side {
+                    Side::Buy => &mut bids,
+                    Side::Sell => &mut asks,
+                };
+                match update.action {
+                    LevelAction::Delete => side.remove(&update.price.units()),
+                    _ => side.insert(update.price.units(), (update.quantity.raw(), update.order_count)),
+                };
+            }
+        }
+
+        let latest = simulator.get_snapshot();
+        assert_eq!(latest.sequence, sequence);
+        assert_eq!((bids, asks), (levels(&latest.bids), levels(&latest.asks)));
+    }
+
+    #[test]
+    fn order_events_rebuild_the_resting_orders() {
+        let mut simulator = MarketSimulator::with_clock(Arc::new(ManualClock::new(0)));
+        let key = |order: &OrderData| (order.order_id, order.side, order.price.units(), order.quantity.raw());
+        let snapshot = simulator.get_snapshot();
+        let mut sequence = snapshot.order_sequence;
+        let mut orders: Vec<_> = snapshot.orders.iter().map(key).collect();
+
+        for _ in 0..100 {
+            let delta = simulator.simulate_market_activity();
+            for event in delta.order_events.iter().filter(|event| event.sequence > snapshot.order_sequence) {
+                assert_eq!(event.sequence, sequence + 1);
+                sequence = event.sequence;
+                let slot = |orders: &Vec<_>, order_id| {
+                    orders.iter().position(|order: &(u64, _, _, _)| order.0 == order_id).expect("known order")
+                };
+                // Orders left displaying nothing stay until the end of the
+                // step, in case a refresh replaces them.
+                match event.kind {
+                    OrderEventDataKind::Added(ref order) => orders.push(key(order)),
+                    OrderEventDataKind::Executed { order_id, remaining, .. }
+                    | OrderEventDataKind::Reduced { order_id, remaining, .. } => {
+                        let slot = slot(&orders, order_id);
+                        orders[slot].3 = remaining.raw();
+                    }
+                    OrderEventDataKind::Replaced { order_id, quantity, keeps_priority, .. } => {
+                        let slot = slot(&orders, order_id);
+                        orders[slot].3 = quantity.raw();
+                        if !keeps_priority {
+                            let order = orders.remove(slot);
+                            orders.push(order);
+                        }
+                    }
+                    OrderEventDataKind::Deleted { order_id } => {
+                        orders.remove(slot(&orders, order_id));
+                    }
+                }
+            }
+            orders.retain(|order| order.3 != 0);
+        }
+
+        // Arrival order is time priority; sort into price priority.
+        orders.sort_by_key(|&(_, side, price, _)| match side {
+            Side::Buy => (0, -price),
+            Side::Sell => (1, price),
+        });
+        let latest = simulator.get_snapshot();
+        assert_eq!(latest.order_sequence, sequence);
+        assert_eq!(orders, latest.orders.iter().map(key).collect::<Vec<_>>());
+    }
+}
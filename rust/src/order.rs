@@ -1,28 +1,259 @@
 use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use serde::{Deserialize, Serialize};
+
+use crate::peg_book::Peg;
+use crate::units::{Price, Qty};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Side {
     Buy,
     Sell,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Side {
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderType {
+    Limit,
+    /// Executes against whatever liquidity is available; `price` is ignored
+    /// and any unfilled remainder is cancelled.
+    Market,
+    /// Becomes a market order once the last trade reaches `stop_price`.
+    StopMarket,
+    /// Becomes a limit order at `price` once the last trade reaches `stop_price`.
+    StopLimit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeInForce {
+    /// Good-till-cancel: rests until filled or cancelled.
+    Gtc,
+    /// Immediate-or-cancel: fills what it can, the remainder is cancelled.
+    Ioc,
+    /// Fill-or-kill: fills completely on entry or not at all.
+    Fok,
+    /// Rests like GTC until the book's day orders are expired.
+    Day,
+    /// Good-till-date: rests until the book's clock reaches this time (ns).
+    Gtd(u64),
+}
+
+/// How a post-only order that would take liquidity on entry is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostOnly {
+    Reject,
+    /// Reprice one tick behind the opposite best price so the order rests.
+    Slide,
+}
+
+/// What happens when an order would trade against a resting order from
+/// the same account. The incoming order's mode applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTradePrevention {
+    CancelNewest,
+    CancelOldest,
+    CancelBoth,
+    /// Reduce both orders by the smaller remaining quantity and cancel
+    /// whichever reaches zero.
+    DecrementAndCancel,
+}
+
+/// Distance a trailing stop keeps from the best last-trade price seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingOffset {
+    Amount(u64),
+    BasisPoints(u64),
+}
+
+pub const BASIS_POINTS_PER_UNIT: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Order {
     pub order_id: u64,
     pub side: Side,
-    pub price: u64,
-    pub quantity: u64,
+    pub price: Price,
+    pub quantity: Qty,
     pub timestamp: u64,
+    pub order_type: OrderType,
+    /// Trigger price for stop orders; unused otherwise.
+    pub stop_price: Price,
+    /// Makes a stop order trail the market, ratcheting `stop_price`.
+    pub trailing_offset: Option<TrailingOffset>,
+    pub time_in_force: TimeInForce,
+    pub account_id: u64,
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    pub post_only: Option<PostOnly>,
+    /// Only ever shrinks the account's net position; quantity beyond the
+    /// position is trimmed on entry.
+    pub reduce_only: bool,
+    /// Iceberg peak: the most quantity shown on the book at once.
+    pub display_quantity: Option<Qty>,
+    /// Iceberg reserve not yet displayed; `quantity` is the visible slice.
+    pub hidden_quantity: Qty,
+    /// Prices the order off the top of book instead of `price`.
+    pub peg: Option<Peg>,
 }
 
 impl Order {
-    pub fn new(order_id: u64, side: Side, price: u64, quantity: u64) -> Self {
+    /// A GTC limit order; `price` and `quantity` are in raw units.
+    pub fn new(order_id: u64, side: Side, price: i64, quantity: u64) -> Self {
         Order {
             order_id,
             side,
-            price,
-            quantity,
+            price: Price::new(price),
+            quantity: Qty::new(quantity),
             timestamp: 0,
+            order_type: OrderType::Limit,
+            stop_price: Price::ZERO,
+            trailing_offset: None,
+            time_in_force: TimeInForce::Gtc,
+            account_id: 0,
+            self_trade_prevention: None,
+            post_only: None,
+            reduce_only: false,
+            display_quantity: None,
+            hidden_quantity: Qty::ZERO,
+            peg: None,
+        }
+    }
+
+    pub fn market(order_id: u64, side: Side, quantity: u64) -> Self {
+        Order {
+            order_type: OrderType::Market,
+            time_in_force: TimeInForce::Ioc,
+            ..Order::new(order_id, side, 0, quantity)
+        }
+    }
+
+    pub fn stop_market(order_id: u64, side: Side, stop_price: i64, quantity: u64) -> Self {
+        Order {
+            order_type: OrderType::StopMarket,
+            stop_price: Price::new(stop_price),
+            ..Order::market(order_id, side, quantity)
+        }
+    }
+
+    pub fn stop_limit(order_id: u64, side: Side, stop_price: i64, price: i64, quantity: u64) -> Self {
+        Order {
+            order_type: OrderType::StopLimit,
+            stop_price: Price::new(stop_price),
+            ..Order::new(order_id, side, price, quantity)
+        }
+    }
+
+    /// A stop-market order whose stop price is set from the last trade on
+    /// entry and then follows favourable trades at `offset`.
+    pub fn trailing_stop(order_id: u64, side: Side, offset: TrailingOffset, quantity: u64) -> Self {
+        Order {
+            trailing_offset: Some(offset),
+            ..Order::stop_market(order_id, side, 0, quantity)
+        }
+    }
+
+    pub fn pegged(order_id: u64, side: Side, peg: Peg, quantity: u64) -> Self {
+        Order {
+            peg: Some(peg),
+            ..Order::new(order_id, side, 0, quantity)
+        }
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    pub fn with_account(mut self, account_id: u64) -> Self {
+        self.account_id = account_id;
+        self
+    }
+
+    pub fn with_self_trade_prevention(mut self, mode: SelfTradePrevention) -> Self {
+        self.self_trade_prevention = Some(mode);
+        self
+    }
+
+    pub fn with_post_only(mut self, mode: PostOnly) -> Self {
+        self.post_only = Some(mode);
+        self
+    }
+
+    pub fn with_reduce_only(mut self) -> Self {
+        self.reduce_only = true;
+        self
+    }
+
+    /// Turns the order into an iceberg showing at most `display_quantity`.
+    pub fn with_display_quantity(mut self, display_quantity: u64) -> Self {
+        self.display_quantity = Some(Qty::new(display_quantity));
+        self
+    }
+
+    /// Displayed plus hidden quantity.
+    pub fn remaining(&self) -> Qty {
+        self.quantity + self.hidden_quantity
+    }
+
+    /// Re-slices the remaining quantity into a displayed peak and a hidden
+    /// reserve. A no-op for orders without a display quantity.
+    pub fn refresh_display(&mut self) {
+        if let Some(peak) = self.display_quantity {
+            let remaining = self.remaining();
+            self.quantity = remaining.min(peak);
+            self.hidden_quantity = remaining - self.quantity;
+        }
+    }
+
+    pub fn is_stop(&self) -> bool {
+        matches!(self.order_type, OrderType::StopMarket | OrderType::StopLimit)
+    }
+
+    /// Stop price a trailing stop would have if `reference` were the best
+    /// price seen so far. Basis points are taken of the reference's
+    /// magnitude; distances beyond the price range saturate.
+    pub fn trailing_stop_price(&self, reference: Price) -> Option<Price> {
+        let distance = match self.trailing_offset? {
+            TrailingOffset::Amount(amount) => amount as u128,
+            TrailingOffset::BasisPoints(bps) => {
+                reference.raw().unsigned_abs() as u128 * bps as u128 / BASIS_POINTS_PER_UNIT as u128
+            }
+        };
+        let distance = Price::new(i64::try_from(distance).unwrap_or(i64::MAX));
+        Some(match self.side {
+            Side::Buy => reference.saturating_add(distance),
+            Side::Sell => reference.saturating_sub(distance),
+        })
+    }
+
+    /// The order that enters matching once a stop is elected.
+    pub fn into_triggered(mut self) -> Self {
+        self.order_type = match self.order_type {
+            OrderType::StopMarket => OrderType::Market,
+            OrderType::StopLimit => OrderType::Limit,
+            other => other,
+        };
+        self
+    }
+
+    /// Whether an unfilled remainder may rest on the book.
+    pub fn can_rest(&self) -> bool {
+        self.order_type == OrderType::Limit
+            && matches!(self.time_in_force, TimeInForce::Gtc | TimeInForce::Day | TimeInForce::Gtd(_))
+    }
+
+    /// Whether this order, as a taker, is willing to trade at `price`.
+    pub fn crosses(&self, price: Price) -> bool {
+        match (self.order_type, self.side) {
+            (OrderType::Market | OrderType::StopMarket, _) => true,
+            (OrderType::Limit | OrderType::StopLimit, Side::Buy) => price <= self.price,
+            (OrderType::Limit | OrderType::StopLimit, Side::Sell) => price >= self.price,
         }
     }
 }
@@ -31,8 +262,14 @@ impl fmt::Display for Order {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Order ID: {}, Side: {:?}, Price: {}, Quantity: {}, Timestamp: {}",
-            self.order_id, self.side, self.price, self.quantity, self.timestamp
+            "Order ID: {}, Side: {:?}, Type: {:?}, TIF: {:?}, Price: {}, Quantity: {}, Timestamp: {}",
+            self.order_id,
+            self.side,
+            self.order_type,
+            self.time_in_force,
+            self.price,
+            self.quantity,
+            self.timestamp
         )
     }
 }
@@ -1,86 +1,2677 @@
 //! Represents the order book.
-use std::collections::{BTreeMap, VecDeque};
-use crate::order::{Order, Side};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::sync::Arc;
+use crate::allocation::{AllocationPolicy, Fifo};
+use crate::auction::{self, AuctionQuote};
+use crate::clock::{Clock, SystemClock};
+use crate::error::BookError;
+use crate::event::{CancelEvent, CancelReason};
+use crate::instrument::Instrument;
+use crate::market_data::{
+    DepthLevel, DepthSnapshot, LevelChanges, LevelUpdate, OrderEvent, OrderEventKind, OrderSnapshot, RestingOrder,
+};
+use crate::order::{Order, OrderType, PostOnly, SelfTradePrevention, Side, TimeInForce, TrailingOffset};
+use crate::peg_book::{self, half_ticks, PegBook, TopOfBook};
+use crate::price_band::{BandWidth, InterruptionAction, PriceBands};
+use crate::price_level::{OrderArena, OrderHandle, PriceLevel};
+use crate::session::{Operation, SessionState};
+use crate::stop_book::{self, StopBook};
+use crate::trade::Trade;
+use crate::units::{Decimal, Price, Qty};
 
-pub struct PriceLevel {
-    pub total_quantity: u64,
-    pub orders: VecDeque<Order>,
-}
+/// Minimum price increment for books without an instrument.
+pub const TICK_SIZE: Price = Price::new(1);
 
-impl PriceLevel {
-    pub fn new() -> Self {
-        PriceLevel {
-            total_quantity: 0,
-            orders: VecDeque::new(),
-        }
-    }
+pub struct OrderBook {
+    bids: BTreeMap<Price, PriceLevel>,
+    asks: BTreeMap<Price, PriceLevel>,
+    arena: OrderArena,
+    index: HashMap<u64, OrderHandle>,
+    /// Net filled quantity by account, wide enough for any fill.
+    positions: HashMap<u64, i128>,
+    stops: StopBook,
+    pegs: PegBook,
+    /// Price of the last trade in half-ticks, exact for midpoint trades.
+    last_trade: Option<i64>,
+    clock: Arc<dyn Clock>,
+    allocation: Arc<dyn AllocationPolicy>,
+    bands: PriceBands,
+    instrument: Option<Instrument>,
+    /// Good-till-date orders by `(expiry, order_id)`; entries for orders
+    /// that have since filled or been cancelled are skipped when popped.
+    expiries: BTreeSet<(u64, u64)>,
+    cancels: Vec<CancelEvent>,
+    /// Set while a call auction is collecting orders, holding its
+    /// reference price if one was given.
+    auction: Option<Option<Price>>,
+    session: SessionState,
+    /// Pending scheduled transitions by `(clock time, order scheduled)`.
+    schedule: BTreeMap<(u64, u64), SessionState>,
+    schedule_count: u64,
+    /// Trades from scheduled transitions not yet returned by `poll_session`.
+    session_trades: Vec<Trade>,
+    /// Set when the book publishes incremental L2 updates.
+    level_changes: Option<LevelChanges>,
+    /// Set when the book publishes L3 order events; holds those not yet drained.
+    order_events: Option<Vec<OrderEvent>>,
+    order_event_sequence: u64,
+    trade_count: u64,
 }
 
-pub struct OrderBook {
-    bids: BTreeMap<u64, PriceLevel>,
-    asks: BTreeMap<u64, PriceLevel>,
+impl Default for OrderBook {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl OrderBook {
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
         OrderBook {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
+            arena: OrderArena::new(),
+            index: HashMap::new(),
+            positions: HashMap::new(),
+            stops: StopBook::new(),
+            pegs: PegBook::new(),
+            last_trade: None,
+            clock,
+            allocation: Arc::new(Fifo),
+            bands: PriceBands::default(),
+            instrument: None,
+            expiries: BTreeSet::new(),
+            cancels: Vec::new(),
+            auction: None,
+            session: SessionState::Continuous,
+            schedule: BTreeMap::new(),
+            schedule_count: 0,
+            session_trades: Vec::new(),
+            level_changes: None,
+            order_events: None,
+            order_event_sequence: 0,
+            trade_count: 0,
         }
     }
 
-    pub fn add_order(&mut self, mut order: Order) {
-        order.timestamp = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_nanos() as u64;
+    /// Replaces the default FIFO allocation within a price level.
+    pub fn with_allocation_policy(mut self, policy: Arc<dyn AllocationPolicy>) -> Self {
+        self.allocation = policy;
+        self
+    }
 
-        let (book_side, price) = match order.side {
-            Side::Buy => (&mut self.bids, order.price),
-            Side::Sell => (&mut self.asks, order.price),
-        };
+    /// Enables static price bands and volatility interruptions. Widths in
+    /// ticks are checked against the book's tick size.
+    pub fn with_price_bands(mut self, bands: PriceBands) -> Result<Self, BookError> {
+        if let Some(width) = bands.too_wide(self.tick_size()) {
+            return Err(BookError::PriceBandTooWide(width));
+        }
+        self.bands = bands;
+        Ok(self)
+    }
 
-        // ...existing code...
-        let price_level = book_side.entry(price).or_insert_with(PriceLevel::new);
-        price_level.total_quantity += order.quantity;
-        price_level.orders.push_back(order);
+    /// Validates new orders and quantity changes against `instrument`'s
+    /// tick, lot, size and notional limits. Rejects an instrument that is
+    /// not valid, or whose tick size makes the price bands too wide.
+    pub fn with_instrument(mut self, instrument: Instrument) -> Result<Self, BookError> {
+        if !instrument.is_valid() {
+            return Err(BookError::InvalidInstrument);
+        }
+        if let Some(width) = self.bands.too_wide(instrument.tick_size) {
+            return Err(BookError::PriceBandTooWide(width));
+        }
+        self.instrument = Some(instrument);
+        Ok(self)
     }
 
-    pub fn cancel_order(&mut self, order_id: u64, side: Side, price: u64) -> bool {
-        let book_side = match side {
+    /// Publishes a sequenced update for every change to a price level;
+    /// see `drain_level_updates`.
+    pub fn with_level_updates(mut self) -> Self {
+        self.level_changes = Some(LevelChanges::default());
+        self
+    }
+
+    /// Publishes an event for every change to a resting order on the
+    /// price levels; see `drain_order_events`.
+    pub fn with_order_events(mut self) -> Self {
+        self.order_events = Some(Vec::new());
+        self
+    }
+
+    pub fn instrument(&self) -> Option<&Instrument> {
+        self.instrument.as_ref()
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn tick_size(&self) -> Price {
+        self.instrument.as_ref().map_or(TICK_SIZE, |instrument| instrument.tick_size)
+    }
+
+    /// Whether the instrument trades at zero and negative prices; books
+    /// without an instrument only accept positive prices. Prices too large
+    /// to write in half-ticks are never accepted.
+    pub fn allows_negative_prices(&self) -> bool {
+        self.instrument.as_ref().is_some_and(|instrument| instrument.allow_negative_prices)
+    }
+
+    fn accepts_price(&self, price: Price) -> bool {
+        (price > Price::ZERO || self.allows_negative_prices()) && peg_book::in_half_tick_range(price)
+    }
+
+    /// Matches `order` against the opposite side in price-time priority and
+    /// rests any unfilled remainder its type and time-in-force allow. Stop
+    /// orders are parked until a trade reaches their stop price, and every
+    /// stop elected by this order's trades executes before returning.
+    /// Pegged orders are priced off the lit top of book and rest outside
+    /// the price levels.
+    /// Returns the executions in fill order; a fill-or-kill order that
+    /// cannot be filled completely is killed without trading. During a
+    /// call auction orders rest without matching until `uncross`.
+    pub fn add_order(&mut self, mut order: Order) -> Result<Vec<Trade>, BookError> {
+        order.timestamp = self.clock.now();
+        self.apply_due_transitions();
+        self.expire_orders();
+        self.check_session(Operation::AddOrder)?;
+        self.validate(&order)?;
+        if order.trailing_offset.is_some() {
+            let last = self.last_trade.ok_or(BookError::NoReferencePrice(order.order_id))?;
+            let reference = stop_book::trailing_reference(order.side, last);
+            order.stop_price = order.trailing_stop_price(reference).expect("trailing order");
+        }
+        let elected_now = self
+            .last_trade
+            .is_some_and(|last| stop_book::is_triggered(&order, last));
+        let parked = order.is_stop() && !elected_now;
+        if self.auction.is_some() && !parked && !joins_auction(&order.into_triggered()) {
+            return Err(BookError::NotAcceptedInAuction(order.order_id));
+        }
+        if let TimeInForce::Gtd(expiry) = order.time_in_force {
+            self.expiries.insert((expiry, order.order_id));
+        }
+
+        if parked {
+            self.stops.insert(order);
+            return Ok(Vec::new());
+        }
+        if self.auction.is_some() {
+            self.rest(order.into_triggered());
+            return Ok(Vec::new());
+        }
+        let mut trades = self.execute(order.into_triggered())?;
+        self.run_cascade(&mut trades);
+        Ok(trades)
+    }
+
+    fn validate(&self, order: &Order) -> Result<(), BookError> {
+        let triggered = order.into_triggered();
+        if order.quantity.is_zero() {
+            return Err(BookError::InvalidQuantity(order.quantity));
+        }
+        if triggered.order_type == OrderType::Limit && order.peg.is_none() && !self.accepts_price(order.price) {
+            return Err(BookError::InvalidPrice(order.price));
+        }
+        match order.trailing_offset {
+            Some(_) if !order.is_stop() => {
+                return Err(BookError::IncompatibleInstructions(order.order_id));
+            }
+            Some(TrailingOffset::Amount(0) | TrailingOffset::BasisPoints(0)) => {
+                return Err(BookError::InvalidPrice(Price::ZERO));
+            }
+            Some(offset @ TrailingOffset::Amount(amount)) if i64::try_from(amount).is_err() => {
+                return Err(BookError::TrailingOffsetOutOfRange { order_id: order.order_id, offset });
+            }
+            Some(_) => {}
+            None if order.is_stop() && !self.accepts_price(order.stop_price) => {
+                return Err(BookError::InvalidPrice(order.stop_price));
+            }
+            None => {}
+        }
+        if let Some(peg) = order.peg.filter(|peg| peg.offset.checked_mul(2).is_none()) {
+            return Err(BookError::PegOffsetOutOfRange { order_id: order.order_id, offset: peg.offset });
+        }
+        if self.index.contains_key(&order.order_id)
+            || self.stops.contains(order.order_id)
+            || self.pegs.contains(order.order_id)
+        {
+            return Err(BookError::DuplicateOrderId(order.order_id));
+        }
+        if let Some(peak) = order.display_quantity {
+            if peak.is_zero() {
+                return Err(BookError::InvalidQuantity(peak));
+            }
+            if !triggered.can_rest() {
+                return Err(BookError::IncompatibleInstructions(order.order_id));
+            }
+        }
+        if order.post_only.is_some() && !triggered.can_rest() {
+            return Err(BookError::IncompatibleInstructions(order.order_id));
+        }
+        let reference = self.bands.reference_price.map(half_ticks).or(self.last_trade);
+        if let (Some(width), Some(reference)) = (self.bands.static_band, reference) {
+            let (low, high) = ticks_within(half_tick_range(width, reference, self.tick_size()));
+            let limit = triggered.order_type == OrderType::Limit && order.peg.is_none();
+            if limit && !(low..=high).contains(&order.price) {
+                let order_id = order.order_id;
+                return Err(BookError::OutsidePriceBand { order_id, price: order.price, low, high });
+            }
+        }
+        if let Some(instrument) = &self.instrument {
+            instrument.validate_order(order, self.notional_price(order))?;
+        }
+        if let TimeInForce::Gtd(expiry) = order.time_in_force {
+            if expiry <= order.timestamp {
+                return Err(BookError::AlreadyExpired(order.order_id));
+            }
+        }
+        let plain_limit = order.order_type == OrderType::Limit
+            && order.time_in_force != TimeInForce::Fok
+            && order.post_only.is_none()
+            && order.display_quantity.is_none();
+        if order.peg.is_some() && !plain_limit {
+            return Err(BookError::IncompatibleInstructions(order.order_id));
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, mut order: Order) -> Result<Vec<Trade>, BookError> {
+        if order.post_only.is_some() {
+            self.apply_post_only(&mut order)?;
+        }
+        if order.reduce_only {
+            self.apply_reduce_only(&mut order)?;
+        }
+
+        if order.time_in_force == TimeInForce::Fok
+            && self.crossing_quantity(&order, order.quantity) < order.quantity
+        {
+            self.record_cancel(&order, order.quantity, CancelReason::Unfilled);
+            return Ok(Vec::new());
+        }
+
+        let (trades, tripped) = self.match_order(&mut order);
+        if tripped {
+            self.interrupt_trading(&mut order);
+        }
+        if !order.quantity.is_zero() && !order.can_rest() {
+            self.record_cancel(&order, order.quantity, CancelReason::Unfilled);
+        }
+
+        if !order.quantity.is_zero() && order.can_rest() && order.peg.is_some() {
+            self.pegs.insert(order);
+        } else if !order.quantity.is_zero() && order.can_rest() {
+            self.rest(order);
+        }
+
+        Ok(trades)
+    }
+
+    /// Queues `order` at the back of its price level.
+    fn rest(&mut self, mut order: Order) {
+        order.refresh_display();
+        self.touch_level(order.side, order.price);
+        let book_side = match order.side {
             Side::Buy => &mut self.bids,
             Side::Sell => &mut self.asks,
         };
+        let price_level = book_side.entry(order.price).or_insert_with(PriceLevel::new);
+        let handle = price_level.push_back(&mut self.arena, order);
+        self.index.insert(order.order_id, handle);
+        self.record_order_event(OrderEventKind::Added(resting_order(&order)));
+    }
+
+    /// Stops continuous matching: from now on orders accumulate, crossed
+    /// or not, until `uncross`. `reference_price` breaks ties between
+    /// equally good clearing prices and defaults to the last trade price.
+    pub fn start_auction(&mut self, reference_price: Option<Price>) {
+        self.auction = Some(reference_price);
+    }
+
+    pub fn is_in_auction(&self) -> bool {
+        self.auction.is_some()
+    }
+
+    /// Where the running auction would clear if it uncrossed now, or
+    /// `None` outside an auction or while nothing would trade.
+    pub fn indicative_uncross(&self) -> Option<AuctionQuote> {
+        let reference = self.auction?.or(self.last_trade_price());
+        let levels = |levels: &BTreeMap<Price, PriceLevel>| -> Vec<(Price, Qty)> {
+            levels
+                .iter()
+                .map(|(&price, level)| (price, level.total_quantity + level.hidden_quantity))
+                .collect()
+        };
+        auction::equilibrium(&levels(&self.bids), &levels(&self.asks), reference)
+    }
 
-        if let Some(price_level) = book_side.get_mut(&price) {
-            if let Some(index) = price_level.orders.iter().position(|o| o.order_id == order_id) {
-                let order = price_level.orders.remove(index).unwrap();
-                price_level.total_quantity -= order.quantity;
-                if price_level.orders.is_empty() {
-                    book_side.remove(&price);
-                }
-                return true;
+    /// Ends the auction by executing every crossing order at the single
+    /// clearing price, best-priced orders first and in time priority
+    /// within a price, then resumes continuous trading. In each trade the
+    /// older order is the maker; self-trade prevention does not apply.
+    /// Stops elected by the clearing price run before returning.
+    pub fn uncross(&mut self) -> Vec<Trade> {
+        let quote = self.indicative_uncross();
+        self.auction = None;
+        let mut trades = Vec::new();
+        let Some(quote) = quote else { return trades };
+
+        let now = self.clock.now();
+        let mut volume = quote.matched_quantity;
+        while !volume.is_zero() {
+            let (&bid_price, bids) = self.bids.last_key_value().expect("bids cross the clearing price");
+            let (&ask_price, asks) = self.asks.first_key_value().expect("asks cross the clearing price");
+            let bid = *self.arena.get(bids.front().expect("levels are never empty"));
+            let ask = *self.arena.get(asks.front().expect("levels are never empty"));
+            let fill = volume.min(bid.remaining()).min(ask.remaining());
+            volume -= fill;
+            let bid_remaining = self.take_front(Side::Buy, bid_price, fill);
+            let ask_remaining = self.take_front(Side::Sell, ask_price, fill);
+
+            let (mut taker, maker) = if (bid.timestamp, bid.order_id) < (ask.timestamp, ask.order_id) {
+                (ask, bid)
+            } else {
+                (bid, ask)
+            };
+            taker.timestamp = now;
+            let trade_id = self.record_trade(&taker, &maker, fill, half_ticks(quote.price), &mut trades);
+            for (order_id, remaining) in [(bid.order_id, bid_remaining), (ask.order_id, ask_remaining)] {
+                let (price, quantity) = (quote.price, fill);
+                self.record_order_event(OrderEventKind::Executed { order_id, trade_id, price, quantity, remaining });
+            }
+        }
+        self.run_cascade(&mut trades);
+        trades
+    }
+
+    /// Books start in continuous trading.
+    pub fn session_state(&self) -> SessionState {
+        self.session
+    }
+
+    /// Moves the session to `next`. Entering pre-open starts a call
+    /// auction and leaving it for continuous trading uncrosses it,
+    /// returning the auction's trades; closing discards an unfinished
+    /// auction's uncross and expires day orders.
+    pub fn set_session_state(&mut self, next: SessionState) -> Result<Vec<Trade>, BookError> {
+        if !self.session.can_transition_to(next) {
+            return Err(BookError::InvalidTransition { from: self.session, to: next });
+        }
+        self.session = next;
+        let trades = match next {
+            SessionState::PreOpen if self.auction.is_none() => {
+                self.start_auction(None);
+                Vec::new()
+            }
+            SessionState::Continuous if self.auction.is_some() => self.uncross(),
+            SessionState::Closed => {
+                self.auction = None;
+                self.expire_day_orders();
+                Vec::new()
+            }
+            _ => Vec::new(),
+        };
+        Ok(trades)
+    }
+
+    /// Queues a transition to `state` once the clock reaches `at`; it
+    /// takes effect on the next `poll_session`, add, cancel or modify.
+    /// Transitions due at the same time apply in the order scheduled.
+    pub fn schedule_session_state(&mut self, at: u64, state: SessionState) {
+        self.schedule_count += 1;
+        self.schedule.insert((at, self.schedule_count), state);
+    }
+
+    /// Applies every scheduled transition the clock has reached, in time
+    /// order, and returns the trades scheduled transitions produced since
+    /// the last call, including those applied by an add, cancel or modify.
+    /// A transition the session can no longer make, for example because it
+    /// was already made through `set_session_state`, is dropped.
+    pub fn poll_session(&mut self) -> Vec<Trade> {
+        self.apply_due_transitions();
+        std::mem::take(&mut self.session_trades)
+    }
+
+    fn apply_due_transitions(&mut self) {
+        let now = self.clock.now();
+        while let Some(entry) = self.schedule.first_entry() {
+            if entry.key().0 > now {
+                break;
             }
+            let state = entry.remove();
+            if let Ok(trades) = self.set_session_state(state) {
+                self.session_trades.extend(trades);
+            }
+        }
+    }
+
+    fn check_session(&self, operation: Operation) -> Result<(), BookError> {
+        if self.session.allows(operation) {
+            Ok(())
+        } else {
+            Err(BookError::SessionRejected { state: self.session, operation })
         }
-        false
     }
 
-    pub fn modify_order(&mut self, order_id: u64, side: Side, price: u64, new_quantity: u64) -> bool {
+    /// Takes `quantity` off the order at the front of a level, displayed
+    /// and hidden alike, without moving it in the queue. Returns what the
+    /// order displays afterwards.
+    fn take_front(&mut self, side: Side, price: Price, quantity: Qty) -> Qty {
+        self.touch_level(side, price);
         let book_side = match side {
             Side::Buy => &mut self.bids,
             Side::Sell => &mut self.asks,
         };
+        let level = book_side.get_mut(&price).expect("level exists");
+        let handle = level.front().expect("levels are never empty");
+        let order = *self.arena.get(handle);
+        if quantity == order.remaining() {
+            level.remove(&mut self.arena, handle);
+            self.index.remove(&order.order_id);
+            if level.is_empty() {
+                book_side.remove(&price);
+            }
+            Qty::ZERO
+        } else {
+            level.update(&mut self.arena, handle, |order| {
+                order.quantity = order.remaining() - quantity;
+                order.hidden_quantity = Qty::ZERO;
+                order.refresh_display();
+            });
+            self.arena.get(handle).quantity
+        }
+    }
+
+    /// Settles everything the executions in `trades` set off: pegged orders
+    /// left crossed by a top-of-book move trade with each other, then
+    /// stops are elected trade by trade and executed in election order,
+    /// appending their fills so they can elect further stops in turn.
+    /// Trailing stops are re-pegged to each trade before it is checked. A
+    /// stop whose elected order is rejected (e.g. reduce-only with no
+    /// position left) is dropped. Once an interruption ends continuous
+    /// trading, stops elected but not yet executed are parked again.
+    fn run_cascade(&mut self, trades: &mut Vec<Trade>) {
+        let mut elected = VecDeque::new();
+        let mut scanned = 0;
+        loop {
+            if self.auction.is_some() || self.session != SessionState::Continuous {
+                for stop in elected {
+                    self.stops.insert(stop);
+                }
+                return;
+            }
+            self.match_crossed_pegs(trades);
+            while scanned < trades.len() {
+                self.stops.update_trailing(trades[scanned].half_tick_price());
+                elected.extend(self.stops.take_triggered(trades[scanned].half_tick_price()));
+                scanned += 1;
+            }
+            let Some(mut stop) = elected.pop_front() else { break };
+            stop.timestamp = trades[scanned - 1].timestamp;
+            if let Ok(fills) = self.execute(stop.into_triggered()) {
+                trades.extend(fills);
+            }
+        }
+    }
+
+    /// Matches `taker` until it is filled or stops crossing. Also returns
+    /// whether matching stopped short of a trade outside the volatility
+    /// band.
+    fn match_order(&mut self, taker: &mut Order) -> (Vec<Trade>, bool) {
+        let mut trades = Vec::new();
+        let side = taker.side;
+        let limit = match (taker.peg, taker.order_type) {
+            (Some(peg), _) => match peg.price(side, self.top_of_book()) {
+                Some(price) => Some(price),
+                None => return (trades, false),
+            },
+            (None, OrderType::Market) => None,
+            (None, _) => Some(half_ticks(taker.price)),
+        };
+        let crosses = |price: i64| match (limit, side) {
+            (None, _) => true,
+            (Some(limit), Side::Buy) => price <= limit,
+            (Some(limit), Side::Sell) => price >= limit,
+        };
+        let band = self.volatility_band();
+        let within_band = |price: i64| band.is_none_or(|(low, high)| (low..=high).contains(&price));
+
+        while !taker.quantity.is_zero() {
+            let lit = self.best_opposite_price(side).map(half_ticks);
+            let peg = self
+                .pegs
+                .best(side.opposite(), self.top_of_book())
+                .map(|(price, maker)| (price, maker.order_id));
+            let peg_first = match (lit, peg) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(lit), Some((peg, _))) => match side {
+                    Side::Buy => peg < lit,
+                    Side::Sell => peg > lit,
+                },
+            };
+
+            if let (true, Some((price, maker_id))) = (peg_first, peg) {
+                if !crosses(price) {
+                    break;
+                }
+                if !within_band(price) {
+                    return (trades, true);
+                }
+                self.fill_peg(taker, maker_id, price, &mut trades);
+            } else {
+                match lit {
+                    Some(price) if crosses(price) && !within_band(price) => return (trades, true),
+                    Some(price) if crosses(price) => self.fill_lit(taker, Price::new(price / 2), &mut trades),
+                    _ => break,
+                }
+            }
+        }
+
+        (trades, false)
+    }
+
+    /// Prices, in half-ticks, that incoming orders may trade at before the
+    /// volatility interruption fires.
+    fn volatility_band(&self) -> Option<(i64, i64)> {
+        let volatility = self.bands.volatility?;
+        Some(half_tick_range(volatility.width, self.last_trade?, self.tick_size()))
+    }
+
+    /// Halts trading, or switches to a short call auction, after `order`
+    /// was stopped at the volatility band. What is left of the order joins
+    /// the auction if it can, and is cancelled otherwise.
+    fn interrupt_trading(&mut self, order: &mut Order) {
+        let Some(volatility) = self.bands.volatility else { return };
+        let _ = self.set_session_state(SessionState::Halted);
+        if let InterruptionAction::Auction { duration } = volatility.action {
+            let _ = self.set_session_state(SessionState::PreOpen);
+            self.schedule_session_state(self.clock.now() + duration, SessionState::Continuous);
+        }
+        if self.auction.is_some() && joins_auction(order) {
+            self.rest(*order);
+        } else {
+            self.record_cancel(order, order.quantity, CancelReason::VolatilityInterruption);
+        }
+        order.quantity = Qty::ZERO;
+    }
+
+    /// Fills `taker` against the level at `price`, sharing its quantity
+    /// among the resting orders as the allocation policy decides. Fills are
+    /// reported in time priority.
+    fn fill_lit(&mut self, taker: &mut Order, price: Price, trades: &mut Vec<Trade>) {
+        let levels = match taker.side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+        };
+        let level = &levels[&price];
+        let allocations = self.allocation.allocate(taker.quantity, &mut level.iter(&self.arena));
+        let fills: Vec<(OrderHandle, Qty)> = level
+            .entries(&self.arena)
+            .map(|(handle, _)| handle)
+            .zip(allocations)
+            .filter(|&(_, fill)| !fill.is_zero())
+            .collect();
+        self.touch_level(taker.side.opposite(), price);
+
+        for (handle, fill) in fills {
+            let maker = *self.arena.get(handle);
+            if let Some(mode) = self_trade_mode(taker, &maker) {
+                self.prevent_self_trade(taker, &maker, mode);
+                return;
+            }
+
+            let book_side = match taker.side {
+                Side::Buy => &mut self.asks,
+                Side::Sell => &mut self.bids,
+            };
+            let price_level = book_side.get_mut(&price).expect("allocated level exists");
+            let fill = fill.min(taker.quantity).min(maker.quantity);
+            taker.quantity -= fill;
+
+            let refreshed = if fill == maker.quantity && !maker.hidden_quantity.is_zero() {
+                let mut refreshed = price_level.remove(&mut self.arena, handle);
+                refreshed.quantity = Qty::ZERO;
+                refreshed.refresh_display();
+                refreshed.timestamp = taker.timestamp;
+                let handle = price_level.push_back(&mut self.arena, refreshed);
+                self.index.insert(maker.order_id, handle);
+                Some(refreshed)
+            } else if fill == maker.quantity {
+                price_level.remove(&mut self.arena, handle);
+                self.index.remove(&maker.order_id);
+                None
+            } else {
+                price_level.set_quantity(&mut self.arena, handle, maker.quantity - fill);
+                None
+            };
+            if price_level.is_empty() {
+                book_side.remove(&price);
+            }
+
+            let trade_id = self.record_trade(taker, &maker, fill, half_ticks(price), trades);
+            self.record_order_event(OrderEventKind::Executed {
+                order_id: maker.order_id,
+                trade_id,
+                price,
+                quantity: fill,
+                remaining: maker.quantity - fill,
+            });
+            if let Some(refreshed) = refreshed {
+                self.record_order_event(OrderEventKind::Replaced {
+                    order_id: refreshed.order_id,
+                    price: refreshed.price,
+                    old_quantity: maker.quantity - fill,
+                    quantity: refreshed.quantity,
+                    keeps_priority: false,
+                });
+            }
+        }
+    }
+
+    /// Fills `taker` against a pegged order priced at `price` half-ticks.
+    fn fill_peg(&mut self, taker: &mut Order, maker_id: u64, price: i64, trades: &mut Vec<Trade>) {
+        let maker = *self.pegs.get(maker_id).expect("best peg is indexed");
+        if let Some(mode) = self_trade_mode(taker, &maker) {
+            self.prevent_self_trade(taker, &maker, mode);
+            return;
+        }
+        let fill = taker.quantity.min(maker.quantity);
+        taker.quantity -= fill;
+        if fill == maker.quantity {
+            self.pegs.remove(maker_id);
+        } else {
+            self.pegs.set_quantity(maker_id, maker.quantity - fill);
+        }
+        self.record_trade(taker, &maker, fill, price, trades);
+    }
+
+    /// Trades resting pegged orders that a top-of-book move has left
+    /// crossed. The older order is the maker and sets the price.
+    fn match_crossed_pegs(&mut self, trades: &mut Vec<Trade>) {
+        loop {
+            let top = self.top_of_book();
+            let Some((bid_price, &bid)) = self.pegs.best(Side::Buy, top) else { return };
+            let Some((ask_price, &ask)) = self.pegs.best(Side::Sell, top) else { return };
+            if bid_price < ask_price {
+                return;
+            }
+            let (mut taker, maker, price) = if (bid.timestamp, bid.order_id) < (ask.timestamp, ask.order_id) {
+                (ask, bid, bid_price)
+            } else {
+                (bid, ask, ask_price)
+            };
+            self.fill_peg(&mut taker, maker.order_id, price, trades);
+            if taker.quantity.is_zero() {
+                self.pegs.remove(taker.order_id);
+            } else {
+                self.pegs.set_quantity(taker.order_id, taker.quantity);
+            }
+        }
+    }
+
+    /// Applies the incoming order's self-trade prevention against a resting
+    /// order from the same account instead of trading with it.
+    fn prevent_self_trade(&mut self, taker: &mut Order, maker: &Order, mode: SelfTradePrevention) {
+        let reason = CancelReason::SelfTradePrevention;
+        match mode {
+            SelfTradePrevention::CancelNewest => {
+                self.record_cancel(taker, taker.quantity, reason);
+                taker.quantity = Qty::ZERO;
+            }
+            SelfTradePrevention::CancelOldest => {
+                self.cancel_resting(maker.order_id, reason);
+            }
+            SelfTradePrevention::CancelBoth => {
+                self.cancel_resting(maker.order_id, reason);
+                self.record_cancel(taker, taker.quantity, reason);
+                taker.quantity = Qty::ZERO;
+            }
+            SelfTradePrevention::DecrementAndCancel => {
+                let decrement = taker.quantity.min(maker.remaining());
+                if decrement == maker.remaining() {
+                    self.cancel_resting(maker.order_id, reason);
+                } else {
+                    self.set_order_quantity(maker.order_id, maker.remaining() - decrement)
+                        .expect("maker is resting");
+                    self.record_cancel(maker, decrement, reason);
+                }
+                self.record_cancel(taker, decrement, reason);
+                taker.quantity -= decrement;
+            }
+        }
+    }
+
+    fn record_cancel(&mut self, order: &Order, quantity: Qty, reason: CancelReason) {
+        self.cancels.push(CancelEvent {
+            order_id: order.order_id,
+            account_id: order.account_id,
+            quantity,
+            reason,
+            timestamp: self.clock.now(),
+        });
+    }
+
+    /// Cancel events recorded since the last call, in the order they happened.
+    pub fn drain_cancels(&mut self) -> Vec<CancelEvent> {
+        std::mem::take(&mut self.cancels)
+    }
+
+    /// Records a fill of `fill` at `price` half-ticks and returns its trade id.
+    fn record_trade(&mut self, taker: &Order, maker: &Order, fill: Qty, price: i64, trades: &mut Vec<Trade>) -> u64 {
+        self.trade_count += 1;
+        let mut trade = Trade::new(taker.order_id, maker.order_id, fill, Price::new(price.div_euclid(2)));
+        trade.trade_id = self.trade_count;
+        trade.half_tick = price.rem_euclid(2) == 1;
+        trade.timestamp = taker.timestamp;
+        trades.push(trade);
+        self.last_trade = Some(price);
+
+        let signed_fill = match taker.side {
+            Side::Buy => fill.raw() as i128,
+            Side::Sell => -(fill.raw() as i128),
+        };
+        let taker_position = self.positions.entry(taker.account_id).or_default();
+        *taker_position = taker_position.saturating_add(signed_fill);
+        let maker_position = self.positions.entry(maker.account_id).or_default();
+        *maker_position = maker_position.saturating_sub(signed_fill);
+        trade.trade_id
+    }
+
+    fn record_order_event(&mut self, kind: OrderEventKind) {
+        if let Some(events) = &mut self.order_events {
+            self.order_event_sequence += 1;
+            events.push(OrderEvent { sequence: self.order_event_sequence, timestamp: self.clock.now(), kind });
+        }
+    }
+
+    /// Order events since the last call, in sequence order. Empty unless
+    /// the book was built `with_order_events`.
+    pub fn drain_order_events(&mut self) -> Vec<OrderEvent> {
+        self.order_events.as_mut().map_or_else(Vec::new, std::mem::take)
+    }
+
+    /// Every resting order on the price levels, in the form the L3 feed
+    /// reports them, stamped with the last order event's sequence number.
+    pub fn order_snapshot(&self) -> OrderSnapshot {
+        OrderSnapshot {
+            sequence: self.order_event_sequence,
+            orders: self.orders(Side::Buy).chain(self.orders(Side::Sell)).map(resting_order).collect(),
+        }
+    }
+
+    /// Rejects or reprices a post-only order that would take liquidity.
+    fn apply_post_only(&self, order: &mut Order) -> Result<(), BookError> {
+        let lit = self.best_opposite_price(order.side).map(half_ticks);
+        let pegged = self.pegs.best(order.side.opposite(), self.top_of_book()).map(|(price, _)| price);
+        let opposite = lit.into_iter().chain(pegged);
+        let best = match order.side {
+            Side::Buy => opposite.min(),
+            Side::Sell => opposite.max(),
+        };
+        let Some(best) = best else { return Ok(()) };
+        let limit = half_ticks(order.price);
+        let crosses = match order.side {
+            Side::Buy => limit >= best,
+            Side::Sell => limit <= best,
+        };
+        if !crosses {
+            return Ok(());
+        }
+        match order.post_only {
+            Some(PostOnly::Slide) => {
+                // The nearest tick strictly on the passive side of `best`,
+                // which may be a pegged order's half-tick price.
+                let tick = self.tick_size().raw();
+                let price = match order.side {
+                    Side::Buy => {
+                        let below = (best - 1).div_euclid(2);
+                        below.checked_sub(below.rem_euclid(tick))
+                    }
+                    Side::Sell => {
+                        let above = (best + 1).div_euclid(2) + (best + 1).rem_euclid(2);
+                        above.checked_add((tick - above.rem_euclid(tick)) % tick)
+                    }
+                };
+                order.price = Price::new(price.ok_or(BookError::InvalidPrice(order.price))?);
+                if !self.accepts_price(order.price) {
+                    return Err(BookError::InvalidPrice(order.price));
+                }
+                Ok(())
+            }
+            _ => Err(BookError::PostOnlyWouldCross(order.order_id)),
+        }
+    }
+
+    /// Trims a reduce-only order to the account's opposing position.
+    fn apply_reduce_only(&self, order: &mut Order) -> Result<(), BookError> {
+        let position = self.position(order.account_id);
+        let reducible = match order.side {
+            Side::Buy if position < 0 => position.unsigned_abs(),
+            Side::Sell if position > 0 => position.unsigned_abs(),
+            _ => 0,
+        };
+        let reducible = Qty::new(u64::try_from(reducible).unwrap_or(u64::MAX));
+        if reducible.is_zero() {
+            return Err(BookError::ReduceOnlyWouldIncrease(order.order_id));
+        }
+        order.quantity = order.quantity.min(reducible);
+        Ok(())
+    }
+
+    /// Net filled quantity for an account: positive long, negative short.
+    pub fn position(&self, account_id: u64) -> i128 {
+        self.positions.get(&account_id).copied().unwrap_or(0)
+    }
+
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.keys().next().copied()
+    }
+
+    /// Best ask minus best bid, while both sides are quoted.
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.best_ask()? - self.best_bid()?)
+    }
+
+    /// Halfway between the best bid and ask, in raw price units. Exact:
+    /// one decimal place holds any half unit.
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let sum = self.best_bid()?.raw() as i128 + self.best_ask()?.raw() as i128;
+        Some(Decimal::new(sum * 5, 1))
+    }
+
+    /// Best bid and ask weighted by the displayed size on the opposite
+    /// side, in raw price units: the price leans towards the side more
+    /// likely to be taken out. A signal for models, not a tradable price,
+    /// so it is the one floating-point figure the book reports.
+    pub fn microprice(&self) -> Option<f64> {
+        let bid = self.depth(Side::Buy, 1).pop()?;
+        let ask = self.depth(Side::Sell, 1).pop()?;
+        let (bid_size, ask_size) = (bid.quantity.raw() as f64, ask.quantity.raw() as f64);
+        let weighted = bid.price.raw() as f64 * ask_size + ask.price.raw() as f64 * bid_size;
+        Some(weighted / (bid_size + ask_size))
+    }
+
+    /// The best `levels` price levels on `side`, best first.
+    pub fn depth(&self, side: Side, levels: usize) -> Vec<DepthLevel> {
+        self.levels(side).take(levels).map(|(&price, level)| depth_level(price, level)).collect()
+    }
+
+    /// Every price level, stamped with the sequence number of the last
+    /// level update it includes. Changes not yet drained are sequenced
+    /// first, so they are still returned by `drain_level_updates`.
+    pub fn depth_snapshot(&mut self) -> DepthSnapshot {
+        self.flush_level_changes();
+        DepthSnapshot {
+            sequence: self.level_changes.as_ref().map_or(0, LevelChanges::sequence),
+            bids: self.depth(Side::Buy, usize::MAX),
+            asks: self.depth(Side::Sell, usize::MAX),
+        }
+    }
+
+    /// Level updates since the last call, in sequence order. Empty unless
+    /// the book was built `with_level_updates`.
+    pub fn drain_level_updates(&mut self) -> Vec<LevelUpdate> {
+        self.flush_level_changes();
+        self.level_changes.as_mut().map_or_else(Vec::new, LevelChanges::take)
+    }
+
+    /// Notes the state of the level at `price` before it changes.
+    fn touch_level(&mut self, side: Side, price: Price) {
+        if let Some(changes) = &mut self.level_changes {
+            let levels = match side {
+                Side::Buy => &self.bids,
+                Side::Sell => &self.asks,
+            };
+            changes.touch(side, price, levels.get(&price).map(|level| depth_level(price, level)));
+        }
+    }
+
+    fn flush_level_changes(&mut self) {
+        let Some(changes) = &mut self.level_changes else { return };
+        let (bids, asks) = (&self.bids, &self.asks);
+        changes.flush(|side, price| {
+            let levels = match side {
+                Side::Buy => bids,
+                Side::Sell => asks,
+            };
+            levels.get(&price).map(|level| depth_level(price, level))
+        });
+    }
+
+    /// Displayed quantity on `side` at `price` or better.
+    pub fn depth_to(&self, side: Side, price: Price) -> Qty {
+        let levels: Box<dyn Iterator<Item = &PriceLevel>> = match side {
+            Side::Buy => Box::new(self.bids.range(price..).map(|(_, level)| level)),
+            Side::Sell => Box::new(self.asks.range(..=price).map(|(_, level)| level)),
+        };
+        levels.map(|level| level.total_quantity).sum()
+    }
+
+    /// Every resting order on `side` in priority order: best price first,
+    /// then time. Parked stops and pegged orders are not on the levels and
+    /// are left out.
+    pub fn orders(&self, side: Side) -> impl Iterator<Item = &Order> + '_ {
+        self.levels(side).flat_map(|(_, level)| level.iter(&self.arena))
+    }
+
+    /// Price levels on `side`, best first.
+    fn levels(&self, side: Side) -> Box<dyn Iterator<Item = (&Price, &PriceLevel)> + '_> {
+        match side {
+            Side::Buy => Box::new(self.bids.iter().rev()),
+            Side::Sell => Box::new(self.asks.iter()),
+        }
+    }
+
+    /// Live orders, counting parked stops and pegged orders.
+    pub fn order_count(&self) -> usize {
+        self.index.len() + self.stops.len() + self.pegs.len()
+    }
+
+    fn top_of_book(&self) -> TopOfBook {
+        TopOfBook {
+            bid: self.best_bid(),
+            ask: self.best_ask(),
+            allow_negative_prices: self.allows_negative_prices(),
+        }
+    }
+
+    /// Current price of a resting pegged order in half-ticks, or `None` if
+    /// it is not pegged or its reference price is missing.
+    pub fn peg_price(&self, order_id: u64) -> Option<i64> {
+        let order = self.pegs.get(order_id)?;
+        order.peg?.price(order.side, self.top_of_book())
+    }
+
+    /// Price `order` is valued at for the instrument's notional limit: its
+    /// limit price, or for market and pegged orders their current peg
+    /// price, else the opposite best, else the last trade.
+    fn notional_price(&self, order: &Order) -> Option<Price> {
+        if order.into_triggered().order_type == OrderType::Limit && order.peg.is_none() {
+            return Some(order.price);
+        }
+        let pegged = order.peg.and_then(|peg| peg.price(order.side, self.top_of_book()));
+        pegged
+            .map(|price| Price::new(price.div_euclid(2) + price.rem_euclid(2)))
+            .or_else(|| self.best_opposite_price(order.side))
+            .or_else(|| self.last_trade_price())
+    }
+
+    fn best_opposite_price(&self, side: Side) -> Option<Price> {
+        match side {
+            Side::Buy => self.asks.keys().next().copied(),
+            Side::Sell => self.bids.keys().next_back().copied(),
+        }
+    }
+
+    /// Opposite-side quantity `taker` could trade against, counted in
+    /// priority order and capped once it reaches `limit`. The match is
+    /// replayed level by level so that pegged orders are counted at the
+    /// price they would have once the levels ahead of them are consumed.
+    /// Under self-trade prevention the taker's own orders do not count,
+    /// and for modes that cut the taker short on meeting one, counting
+    /// stops at the first level holding one, or at once if one of its
+    /// pegged orders rests opposite.
+    fn crossing_quantity(&self, taker: &Order, limit: Qty) -> Qty {
+        let own = |order: &Order| self_trade_mode(taker, order).is_some();
+        let cut_short = taker
+            .self_trade_prevention
+            .is_some_and(|mode| mode != SelfTradePrevention::CancelOldest);
+        if cut_short && self.pegs.orders(taker.side.opposite()).any(own) {
+            return Qty::ZERO;
+        }
+        let band = self.volatility_band();
+        let crosses = |price: i64| {
+            let within_band = band.is_none_or(|(low, high)| (low..=high).contains(&price));
+            within_band
+                && match (taker.order_type, taker.side) {
+                    (OrderType::Market, _) => true,
+                    (_, Side::Buy) => price <= half_ticks(taker.price),
+                    (_, Side::Sell) => price >= half_ticks(taker.price),
+                }
+        };
+
+        // Crossing levels as `(price, quantity)`, `None` for a level where
+        // self-trade prevention would cut the taker short.
+        let mut levels = Vec::new();
+        let mut lit = Qty::ZERO;
+        for (&price, level) in self.levels(taker.side.opposite()) {
+            if !crosses(half_ticks(price)) || lit >= limit {
+                break;
+            }
+            let quantity = if taker.self_trade_prevention.is_none() {
+                level.total_quantity + level.hidden_quantity
+            } else if cut_short && level.iter(&self.arena).any(own) {
+                levels.push((price, None));
+                break;
+            } else {
+                level.iter(&self.arena).filter(|order| !own(order)).map(Order::remaining).sum()
+            };
+            lit = lit.saturating_add(quantity);
+            levels.push((price, Some(quantity)));
+        }
+        let mut pegs: Vec<Order> = self.pegs.orders(taker.side.opposite()).filter(|order| !own(order)).copied().collect();
 
-        if let Some(price_level) = book_side.get_mut(&price) {
-            for order in &mut price_level.orders {
-                if order.order_id == order_id {
-                    price_level.total_quantity -= order.quantity;
-                    order.quantity = new_quantity;
-                    price_level.total_quantity += new_quantity;
-                    return true;
+        let mut top = self.top_of_book();
+        let mut available = Qty::ZERO;
+        let mut next_level = levels.iter();
+        let mut level = next_level.next();
+        while available < limit {
+            let opposite = level.map(|&(price, _)| price);
+            match taker.side {
+                Side::Buy => top.ask = opposite,
+                Side::Sell => top.bid = opposite,
+            }
+            let peg = pegs
+                .iter_mut()
+                .filter_map(|order| Some((order.peg?.price(order.side, top)?, order)))
+                .min_by_key(|(price, order)| {
+                    let price = match taker.side {
+                        Side::Buy => *price,
+                        Side::Sell => -*price,
+                    };
+                    (price, order.timestamp, order.order_id)
+                });
+            let peg_first = match (opposite.map(half_ticks), &peg) {
+                (_, None) => false,
+                (None, Some(_)) => true,
+                (Some(lit), Some((peg, _))) => match taker.side {
+                    Side::Buy => *peg < lit,
+                    Side::Sell => *peg > lit,
+                },
+            };
+            match (peg, level) {
+                (Some((price, order)), _) if peg_first => {
+                    if !crosses(price) {
+                        break;
+                    }
+                    available = available.saturating_add(order.remaining());
+                    // Consumed: without a peg it is no longer priced.
+                    order.peg = None;
+                }
+                (_, Some(&(_, Some(quantity)))) => {
+                    available = available.saturating_add(quantity);
+                    level = next_level.next();
                 }
+                _ => break,
             }
         }
-        false
+        available
     }
-}
\ No newline at end of file
+
+    /// Cancels every resting `TimeInForce::Day` order, e.g. at the close,
+    /// parked stops and pegged orders included.
+    pub fn expire_day_orders(&mut self) -> Vec<Order> {
+        let resting = self.index.values().map(|&handle| self.arena.get(handle));
+        let pegged = self.pegs.orders(Side::Buy).chain(self.pegs.orders(Side::Sell));
+        let mut expired: Vec<u64> = resting
+            .chain(self.stops.orders())
+            .chain(pegged)
+            .filter(|order| order.time_in_force == TimeInForce::Day)
+            .map(|order| order.order_id)
+            .collect();
+        expired.sort_unstable();
+        expired
+            .into_iter()
+            .filter_map(|order_id| self.cancel_resting(order_id, CancelReason::Expired))
+            .collect()
+    }
+
+    /// Cancels every good-till-date order whose expiry the clock has
+    /// reached, earliest expiry first. Runs on each `add_order`; call it
+    /// directly to expire orders between order arrivals.
+    pub fn expire_orders(&mut self) -> Vec<Order> {
+        let now = self.clock.now();
+        let mut expired = Vec::new();
+        while let Some(&(expiry, order_id)) = self.expiries.first() {
+            if expiry > now {
+                break;
+            }
+            self.expiries.pop_first();
+            let still_live = self
+                .find_order(order_id)
+                .is_some_and(|o| o.time_in_force == TimeInForce::Gtd(expiry));
+            if still_live {
+                expired.extend(self.cancel_resting(order_id, CancelReason::Expired));
+            }
+        }
+        expired
+    }
+
+    fn find_order(&self, order_id: u64) -> Option<&Order> {
+        self.index
+            .get(&order_id)
+            .map(|&handle| self.arena.get(handle))
+            .or_else(|| self.stops.get(order_id))
+            .or_else(|| self.pegs.get(order_id))
+    }
+
+    /// Price of the last trade; a midpoint trade between two ticks
+    /// reports the lower one.
+    pub fn last_trade_price(&self) -> Option<Price> {
+        self.last_trade.map(|price| Price::new(price.div_euclid(2)))
+    }
+
+    /// Removes a resting order, locating it through the order-id index.
+    /// Pending stop and pegged orders are cancelled the same way. Due
+    /// scheduled transitions apply first, and an order whose expiry has
+    /// passed is expired and reported as unknown.
+    pub fn cancel_order_by_id(&mut self, order_id: u64) -> Result<Order, BookError> {
+        self.apply_due_transitions();
+        self.expire_orders();
+        self.check_session(Operation::CancelOrder)?;
+        let order = self.remove_order(order_id)?;
+        self.record_cancel(&order, order.remaining(), CancelReason::Requested);
+        Ok(order)
+    }
+
+    fn cancel_resting(&mut self, order_id: u64, reason: CancelReason) -> Option<Order> {
+        let order = self.remove_order(order_id).ok()?;
+        self.record_cancel(&order, order.remaining(), reason);
+        Some(order)
+    }
+
+    fn remove_order(&mut self, order_id: u64) -> Result<Order, BookError> {
+        if let Some(stop) = self.stops.remove(order_id) {
+            return Ok(stop);
+        }
+        if let Some(pegged) = self.pegs.remove(order_id) {
+            return Ok(pegged);
+        }
+        let handle = self.index.remove(&order_id).ok_or(BookError::UnknownOrder(order_id))?;
+        let Order { side, price, .. } = *self.arena.get(handle);
+        self.touch_level(side, price);
+        let book_side = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+
+        let price_level = book_side.get_mut(&price).expect("indexed order has a price level");
+        let order = price_level.remove(&mut self.arena, handle);
+        if price_level.is_empty() {
+            book_side.remove(&price);
+        }
+        self.record_order_event(OrderEventKind::Deleted { order_id });
+        Ok(order)
+    }
+
+    /// Changes a resting order's quantity in place without losing time
+    /// priority; parked stops and pegged orders too. For icebergs
+    /// `new_quantity` is the total, displayed plus hidden. Like cancels,
+    /// modifies apply due transitions and expiries first.
+    pub fn modify_order_by_id(&mut self, order_id: u64, new_quantity: Qty) -> Result<(), BookError> {
+        self.apply_due_transitions();
+        self.expire_orders();
+        self.check_session(Operation::ModifyOrder)?;
+        if new_quantity.is_zero() {
+            return Err(BookError::InvalidQuantity(new_quantity));
+        }
+        if let Some(instrument) = &self.instrument {
+            let order = self.find_order(order_id).ok_or(BookError::UnknownOrder(order_id))?;
+            instrument.validate_quantity(order, new_quantity, self.notional_price(order))?;
+        }
+        self.set_order_quantity(order_id, new_quantity)
+    }
+
+    /// `modify_order_by_id` without the session and instrument checks, for
+    /// changes the book makes itself.
+    fn set_order_quantity(&mut self, order_id: u64, new_quantity: Qty) -> Result<(), BookError> {
+        if self.stops.set_quantity(order_id, new_quantity) || self.pegs.set_quantity(order_id, new_quantity) {
+            return Ok(());
+        }
+        let handle = *self.index.get(&order_id).ok_or(BookError::UnknownOrder(order_id))?;
+        let Order { side, price, .. } = *self.arena.get(handle);
+        self.touch_level(side, price);
+        let book_side = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+
+        let price_level = book_side.get_mut(&price).expect("indexed order has a price level");
+        let before = self.arena.get(handle).quantity;
+        price_level.update(&mut self.arena, handle, |order| {
+            order.quantity = new_quantity;
+            order.hidden_quantity = Qty::ZERO;
+            order.refresh_display();
+        });
+        let after = self.arena.get(handle).quantity;
+        if after < before {
+            self.record_order_event(OrderEventKind::Reduced { order_id, quantity: before - after, remaining: after });
+        } else if after > before {
+            self.record_order_event(OrderEventKind::Replaced {
+                order_id,
+                price,
+                old_quantity: before,
+                quantity: after,
+                keeps_priority: true,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn cancel_order(&mut self, order_id: u64, side: Side, price: Price) -> Result<Order, BookError> {
+        self.check_resting_at(order_id, side, price)?;
+        self.cancel_order_by_id(order_id)
+    }
+
+    pub fn modify_order(
+        &mut self,
+        order_id: u64,
+        side: Side,
+        price: Price,
+        new_quantity: Qty,
+    ) -> Result<(), BookError> {
+        self.check_resting_at(order_id, side, price)?;
+        self.modify_order_by_id(order_id, new_quantity)
+    }
+
+    fn check_resting_at(&self, order_id: u64, side: Side, price: Price) -> Result<(), BookError> {
+        let handle = *self.index.get(&order_id).ok_or(BookError::UnknownOrder(order_id))?;
+        let order = self.arena.get(handle);
+        if order.side != side {
+            return Err(BookError::SideMismatch { order_id, expected: side, actual: order.side });
+        }
+        if order.price != price {
+            return Err(BookError::PriceMismatch { order_id, expected: price, actual: order.price });
+        }
+        Ok(())
+    }
+}
+
+/// Whether `order` may join a call auction: it has to be able to rest at
+/// its own price until the uncross.
+fn joins_auction(order: &Order) -> bool {
+    order.can_rest() && order.peg.is_none() && order.post_only.is_none() && !order.reduce_only
+}
+
+/// `width` around `reference`, both in half-ticks.
+fn half_tick_range(width: BandWidth, reference: i64, tick_size: Price) -> (i64, i64) {
+    let (low, high) = width.range(Price::new(reference), tick_size.saturating_add(tick_size));
+    (low.raw(), high.raw())
+}
+
+/// The tick prices inside a half-tick range.
+fn ticks_within((low, high): (i64, i64)) -> (Price, Price) {
+    (Price::new(low.div_euclid(2) + low.rem_euclid(2)), Price::new(high.div_euclid(2)))
+}
+
+fn resting_order(order: &Order) -> RestingOrder {
+    RestingOrder { order_id: order.order_id, side: order.side, price: order.price, quantity: order.quantity }
+}
+
+fn depth_level(price: Price, level: &PriceLevel) -> DepthLevel {
+    DepthLevel { price, quantity: level.total_quantity, order_count: level.len() }
+}
+
+fn self_trade_mode(taker: &Order, maker: &Order) -> Option<SelfTradePrevention> {
+    taker
+        .self_trade_prevention
+        .filter(|_| taker.account_id == maker.account_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OrderBook;
+    use crate::allocation::{ProRata, Remainder};
+    use crate::auction::AuctionQuote;
+    use crate::clock::ManualClock;
+    use crate::error::BookError;
+    use crate::event::CancelReason;
+    use crate::instrument::Instrument;
+    use crate::market_data::{DepthReplica, LevelAction, OrderEventKind, RestingOrder};
+    use crate::order::{Order, PostOnly, SelfTradePrevention, Side, TimeInForce, TrailingOffset};
+    use crate::peg_book::{Peg, PegReference};
+    use crate::price_band::{BandWidth, InterruptionAction, PriceBands, VolatilityInterruption};
+    use crate::session::{Operation, SessionState};
+    use crate::units::{Price, Qty};
+    use std::sync::Arc;
+
+    #[test]
+    fn non_crossing_orders_rest() {
+        let mut book = OrderBook::new();
+        assert!(book.add_order(Order::new(1, Side::Buy, 99, 10)).unwrap().is_empty());
+        assert!(book.add_order(Order::new(2, Side::Sell, 101, 10)).unwrap().is_empty());
+        assert_eq!(book.bids[&Price::new(99)].total_quantity, 10);
+        assert_eq!(book.asks[&Price::new(101)].total_quantity, 10);
+    }
+
+    #[test]
+    fn matches_in_price_then_time_priority() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 101, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 100, 5)).unwrap();
+
+        let trades = book.add_order(Order::new(4, Side::Buy, 101, 12)).unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|t| (t.taker_order_id, t.maker_order_id, t.price.raw(), t.quantity.raw()))
+            .collect();
+        assert_eq!(fills, vec![(4, 2, 100, 5), (4, 3, 100, 5), (4, 1, 101, 2)]);
+        assert!(trades.iter().all(|t| t.timestamp > 0));
+
+        assert!(!book.asks.contains_key(&Price::new(100)));
+        assert_eq!(book.asks[&Price::new(101)].total_quantity, 3);
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn partial_fill_rests_remainder_at_limit() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 4)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 98, 4)).unwrap();
+
+        let trades = book.add_order(Order::new(3, Side::Sell, 99, 10)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].maker_order_id, trades[0].price.raw(), trades[0].quantity.raw()), (1, 100, 4));
+
+        assert_eq!(book.asks[&Price::new(99)].total_quantity, 6);
+        assert_eq!(book.asks[&Price::new(99)].iter(&book.arena).next().unwrap().order_id, 3);
+        assert_eq!(book.bids[&Price::new(98)].total_quantity, 4);
+    }
+
+    #[test]
+    fn reads_top_of_book_depth_and_orders() {
+        let mut book = OrderBook::new();
+        assert_eq!((book.spread(), book.mid_price(), book.microprice()), (None, None, None));
+        book.add_order(Order::new(1, Side::Buy, 100, 4)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 2)).unwrap();
+        book.add_order(Order::new(3, Side::Buy, 99, 5)).unwrap();
+        book.add_order(Order::new(4, Side::Sell, 102, 2)).unwrap();
+        book.add_order(Order::new(5, Side::Sell, 103, 10).with_display_quantity(3)).unwrap();
+
+        assert_eq!(book.spread(), Some(Price::new(2)));
+        assert_eq!(book.mid_price().map(|mid| mid.to_string()), Some("101.0".to_string()));
+        assert_eq!(book.microprice(), Some(101.5));
+        book.add_order(Order::new(6, Side::Sell, 101, 1)).unwrap();
+        assert_eq!(book.mid_price().map(|mid| mid.to_string()), Some("100.5".to_string()));
+        book.cancel_order(6, Side::Sell, Price::new(101)).unwrap();
+
+        let levels: Vec<_> = book
+            .depth(Side::Buy, 5)
+            .iter()
+            .map(|level| (level.price.raw(), level.quantity.raw(), level.order_count))
+            .collect();
+        assert_eq!(levels, vec![(100, 6, 2), (99, 5, 1)]);
+        assert_eq!(book.depth(Side::Sell, 1)[0].price, Price::new(102));
+        assert_eq!(book.depth_to(Side::Buy, Price::new(99)), 11);
+        assert_eq!(book.depth_to(Side::Sell, Price::new(103)), 5);
+        assert_eq!(book.depth_to(Side::Sell, Price::new(101)), 0);
+
+        let bids: Vec<_> = book.orders(Side::Buy).map(|o| o.order_id).collect();
+        let asks: Vec<_> = book.orders(Side::Sell).map(|o| o.order_id).collect();
+        assert_eq!((bids, asks), (vec![1, 2, 3], vec![4, 5]));
+    }
+
+    #[test]
+    fn level_updates_keep_a_replica_in_step() {
+        let mut book = OrderBook::new().with_level_updates();
+        book.add_order(Order::new(1, Side::Buy, 100, 4)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 102, 10).with_display_quantity(3)).unwrap();
+        let snapshot = book.depth_snapshot();
+        assert_eq!(snapshot.sequence, 2);
+        let mut replica = DepthReplica::new(&snapshot);
+
+        book.add_order(Order::new(3, Side::Buy, 100, 2)).unwrap();
+        book.add_order(Order::new(4, Side::Buy, 102, 5)).unwrap();
+        book.add_order(Order::new(5, Side::Buy, 99, 1)).unwrap();
+        book.cancel_order_by_id(5).unwrap();
+        book.modify_order_by_id(1, Qty::new(1)).unwrap();
+        book.add_order(Order::new(6, Side::Sell, 100, 3)).unwrap();
+
+        let updates = book.drain_level_updates();
+        let actions: Vec<_> = updates
+            .iter()
+            .map(|u| (u.sequence, u.side, u.action, u.price.raw(), u.quantity.raw(), u.order_count))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![
+                (1, Side::Buy, LevelAction::Add, 100, 4, 1),
+                (2, Side::Sell, LevelAction::Add, 102, 3, 1),
+                (3, Side::Buy, LevelAction::Delete, 100, 0, 0),
+                (4, Side::Sell, LevelAction::Update, 102, 1, 1),
+            ]
+        );
+        for update in &updates {
+            replica.apply(update).unwrap();
+        }
+        assert_eq!(replica.depth(Side::Buy, 10), book.depth(Side::Buy, 10));
+        assert_eq!(replica.depth(Side::Sell, 10), book.depth(Side::Sell, 10));
+        assert!(book.drain_level_updates().is_empty());
+        assert!(OrderBook::new().drain_level_updates().is_empty());
+    }
+
+    #[test]
+    fn order_events_follow_every_resting_order() {
+        let mut book = OrderBook::new().with_order_events();
+        book.add_order(Order::new(1, Side::Sell, 100, 10).with_display_quantity(4)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 5)).unwrap();
+        let trades = book.add_order(Order::new(3, Side::Buy, 100, 6)).unwrap();
+        assert_eq!(trades.iter().map(|t| t.trade_id).collect::<Vec<_>>(), vec![1, 2]);
+        book.modify_order_by_id(2, Qty::new(1)).unwrap();
+        book.modify_order_by_id(2, Qty::new(4)).unwrap();
+        book.cancel_order_by_id(1).unwrap();
+
+        let resting = |order_id, quantity| RestingOrder {
+            order_id,
+            side: Side::Sell,
+            price: Price::new(100),
+            quantity: Qty::new(quantity),
+        };
+        let added = |order_id, quantity| OrderEventKind::Added(resting(order_id, quantity));
+        let executed = |order_id, trade_id, quantity, remaining| OrderEventKind::Executed {
+            order_id,
+            trade_id,
+            price: Price::new(100),
+            quantity: Qty::new(quantity),
+            remaining: Qty::new(remaining),
+        };
+        let replaced = |order_id, old_quantity, quantity, keeps_priority| OrderEventKind::Replaced {
+            order_id,
+            price: Price::new(100),
+            old_quantity: Qty::new(old_quantity),
+            quantity: Qty::new(quantity),
+            keeps_priority,
+        };
+        let events = book.drain_order_events();
+        assert_eq!(events.iter().map(|e| e.sequence).collect::<Vec<_>>(), (1..=8).collect::<Vec<_>>());
+        assert_eq!(
+            events.iter().map(|e| e.kind).collect::<Vec<_>>(),
+            vec![
+                added(1, 4),
+                added(2, 5),
+                executed(1, 1, 4, 0),
+                replaced(1, 0, 4, false),
+                executed(2, 2, 2, 3),
+                OrderEventKind::Reduced { order_id: 2, quantity: Qty::new(2), remaining: Qty::new(1) },
+                replaced(2, 1, 4, true),
+                OrderEventKind::Deleted { order_id: 1 },
+            ]
+        );
+
+        let snapshot = book.order_snapshot();
+        assert_eq!((snapshot.sequence, snapshot.orders), (8, vec![resting(2, 4)]));
+        assert!(book.drain_order_events().is_empty());
+    }
+
+    #[test]
+    fn cancel_by_id_unlinks_from_middle_of_queue() {
+        let mut book = OrderBook::new();
+        for id in 1..=3 {
+            book.add_order(Order::new(id, Side::Buy, 100, 10)).unwrap();
+        }
+
+        assert_eq!(book.cancel_order_by_id(2).unwrap().order_id, 2);
+        assert_eq!(book.cancel_order_by_id(2).unwrap_err(), BookError::UnknownOrder(2));
+        let level = &book.bids[&Price::new(100)];
+        assert_eq!(level.total_quantity.raw(), 20);
+        let ids: Vec<_> = level.iter(&book.arena).map(|o| o.order_id).collect();
+        assert_eq!(ids, vec![1, 3]);
+
+        assert!(book.cancel_order_by_id(1).is_ok());
+        assert!(book.cancel_order_by_id(3).is_ok());
+        assert!(book.bids.is_empty());
+        assert!(book.index.is_empty());
+    }
+
+    #[test]
+    fn modify_by_id_keeps_time_priority() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 10)).unwrap();
+
+        assert!(book.modify_order_by_id(1, Qty::new(3)).is_ok());
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 13);
+
+        let trades = book.add_order(Order::new(3, Side::Buy, 100, 5)).unwrap();
+        assert_eq!((trades[0].maker_order_id, trades[0].quantity.raw()), (1, 3));
+        assert_eq!((trades[1].maker_order_id, trades[1].quantity.raw()), (2, 2));
+    }
+
+    #[test]
+    fn wrappers_require_matching_side_and_price() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+
+        assert_eq!(
+            book.cancel_order(1, Side::Sell, Price::new(100)),
+            Err(BookError::SideMismatch { order_id: 1, expected: Side::Sell, actual: Side::Buy })
+        );
+        assert_eq!(
+            book.modify_order(1, Side::Buy, Price::new(101), Qty::new(5)),
+            Err(BookError::PriceMismatch { order_id: 1, expected: Price::new(101), actual: Price::new(100) })
+        );
+        assert!(book.modify_order(1, Side::Buy, Price::new(100), Qty::new(5)).is_ok());
+        assert_eq!(book.cancel_order(1, Side::Buy, Price::new(100)).unwrap().quantity, 5);
+    }
+
+    #[test]
+    fn filled_makers_leave_the_index() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 10)).unwrap();
+
+        assert_eq!(book.cancel_order_by_id(1), Err(BookError::UnknownOrder(1)));
+        assert!(book.asks.is_empty() && book.bids.is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_orders() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+
+        assert_eq!(book.add_order(Order::new(1, Side::Buy, 100, 10)), Err(BookError::DuplicateOrderId(1)));
+        assert_eq!(book.add_order(Order::new(2, Side::Buy, 100, 0)), Err(BookError::InvalidQuantity(Qty::new(0))));
+        assert_eq!(book.add_order(Order::new(3, Side::Sell, 0, 10)), Err(BookError::InvalidPrice(Price::new(0))));
+        assert_eq!(book.modify_order_by_id(1, Qty::new(0)), Err(BookError::InvalidQuantity(Qty::new(0))));
+        assert_eq!(book.modify_order_by_id(9, Qty::new(5)), Err(BookError::UnknownOrder(9)));
+        assert_eq!(book.bids[&Price::new(100)].total_quantity, 10);
+    }
+
+    #[test]
+    fn market_order_sweeps_and_never_rests() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 150, 5)).unwrap();
+
+        let trades = book.add_order(Order::market(3, Side::Buy, 20)).unwrap();
+        let prices: Vec<_> = trades.iter().map(|t| (t.price.raw(), t.quantity.raw())).collect();
+        assert_eq!(prices, vec![(100, 5), (150, 5)]);
+        assert!(book.asks.is_empty() && book.bids.is_empty());
+        assert_eq!(book.cancel_order_by_id(3), Err(BookError::UnknownOrder(3)));
+    }
+
+    #[test]
+    fn ioc_cancels_unfilled_remainder() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 5)).unwrap();
+
+        let ioc = Order::new(2, Side::Sell, 100, 8).with_time_in_force(TimeInForce::Ioc);
+        let trades = book.add_order(ioc).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity.raw(), 5);
+        assert!(book.asks.is_empty() && book.bids.is_empty());
+    }
+
+    #[test]
+    fn fok_is_all_or_nothing() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 101, 5)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 102, 5)).unwrap();
+
+        let too_big = Order::new(4, Side::Buy, 101, 11).with_time_in_force(TimeInForce::Fok);
+        assert!(book.add_order(too_big).unwrap().is_empty());
+        assert_eq!(book.asks.values().map(|l| l.total_quantity).sum::<Qty>(), 15);
+
+        let fits = Order::new(5, Side::Buy, 101, 10).with_time_in_force(TimeInForce::Fok);
+        let trades = book.add_order(fits).unwrap();
+        assert_eq!(trades.iter().map(|t| t.quantity.raw()).sum::<u64>(), 10);
+        assert_eq!(book.asks.keys().copied().collect::<Vec<_>>(), vec![102]);
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn fok_counts_pegged_liquidity_at_its_price_after_the_sweep() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 99, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 102, 5)).unwrap();
+        let midpoint = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(Order::pegged(3, Side::Buy, midpoint, 10)).unwrap();
+
+        let fok = |id, price, quantity| Order::new(id, Side::Sell, price, quantity).with_time_in_force(TimeInForce::Fok);
+        let trades = book.add_order(fok(4, 99, 15)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.maker_order_id, t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(3, 10), (1, 5)]);
+
+        // Once the bid at 100 is swept the primary peg has no reference.
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 5)).unwrap();
+        let primary = Peg { reference: PegReference::Primary, offset: -1 };
+        book.add_order(Order::pegged(2, Side::Buy, primary, 5)).unwrap();
+        assert!(book.add_order(fok(3, 99, 10)).unwrap().is_empty());
+        assert_eq!(book.add_order(fok(4, 99, 5)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn fok_does_not_count_liquidity_self_trade_prevention_removes() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5).with_account(7)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 5).with_account(8)).unwrap();
+
+        let fok = |id, quantity, mode| {
+            Order::new(id, Side::Buy, 100, quantity)
+                .with_account(7)
+                .with_time_in_force(TimeInForce::Fok)
+                .with_self_trade_prevention(mode)
+        };
+        assert!(book.add_order(fok(3, 10, SelfTradePrevention::CancelOldest)).unwrap().is_empty());
+        assert!(book.add_order(fok(4, 5, SelfTradePrevention::CancelNewest)).unwrap().is_empty());
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 10);
+
+        let trades = book.add_order(fok(5, 5, SelfTradePrevention::CancelOldest)).unwrap();
+        assert_eq!(trades.iter().map(|t| (t.maker_order_id, t.quantity.raw())).collect::<Vec<_>>(), vec![(2, 5)]);
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn day_orders_expire_at_close() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 5).with_time_in_force(TimeInForce::Day)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 5)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 105, 5).with_time_in_force(TimeInForce::Day)).unwrap();
+
+        let expired: Vec<_> = book.expire_day_orders().iter().map(|o| o.order_id).collect();
+        assert_eq!(expired, vec![1, 3]);
+        assert_eq!(book.bids[&Price::new(100)].total_quantity, 5);
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn post_only_reject_and_slide() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 97, 5)).unwrap();
+
+        let crossing = Order::new(3, Side::Buy, 101, 5).with_post_only(PostOnly::Reject);
+        assert_eq!(book.add_order(crossing), Err(BookError::PostOnlyWouldCross(3)));
+
+        let passive = Order::new(4, Side::Buy, 98, 5).with_post_only(PostOnly::Reject);
+        assert!(book.add_order(passive).unwrap().is_empty());
+
+        let sliding = Order::new(5, Side::Buy, 101, 5).with_post_only(PostOnly::Slide);
+        assert!(book.add_order(sliding).unwrap().is_empty());
+        assert_eq!(book.bids[&Price::new(99)].total_quantity, 5);
+
+        let sliding_sell = Order::new(6, Side::Sell, 90, 5).with_post_only(PostOnly::Slide);
+        assert!(book.add_order(sliding_sell).unwrap().is_empty());
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 10);
+
+        let post_only_ioc = Order::new(7, Side::Buy, 90, 5)
+            .with_post_only(PostOnly::Reject)
+            .with_time_in_force(TimeInForce::Ioc);
+        assert_eq!(book.add_order(post_only_ioc), Err(BookError::IncompatibleInstructions(7)));
+    }
+
+    #[test]
+    fn positions_hold_fills_beyond_i64() {
+        let mut book = OrderBook::new();
+        let huge = u64::MAX - 5;
+        book.add_order(Order::new(1, Side::Sell, 100, huge).with_account(2)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, huge).with_account(1)).unwrap();
+        assert_eq!((book.position(1), book.position(2)), (huge as i128, -(huge as i128)));
+
+        book.add_order(Order::new(3, Side::Buy, 101, 10).with_account(3)).unwrap();
+        let reducing = Order::new(4, Side::Sell, 101, 4).with_account(1).with_reduce_only();
+        assert_eq!(book.add_order(reducing).unwrap()[0].quantity.raw(), 4);
+        assert_eq!(book.position(1), huge as i128 - 4);
+    }
+
+    #[test]
+    fn reduce_only_is_capped_by_position() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 10).with_account(2)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 6).with_account(1)).unwrap();
+        assert_eq!(book.position(1), 6);
+        assert_eq!(book.position(2), -6);
+
+        let increase = Order::new(3, Side::Buy, 100, 1).with_account(1).with_reduce_only();
+        assert_eq!(book.add_order(increase), Err(BookError::ReduceOnlyWouldIncrease(3)));
+
+        let reduce = Order::new(4, Side::Sell, 110, 50).with_account(1).with_reduce_only();
+        book.add_order(reduce).unwrap();
+        assert_eq!(book.asks[&Price::new(110)].total_quantity, 6);
+    }
+
+    #[test]
+    fn iceberg_displays_only_its_peak() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 25).with_display_quantity(10)).unwrap();
+
+        let level = &book.asks[&Price::new(100)];
+        assert_eq!((level.total_quantity.raw(), level.hidden_quantity.raw()), (10, 15));
+
+        book.modify_order_by_id(1, Qty::new(12)).unwrap();
+        let level = &book.asks[&Price::new(100)];
+        assert_eq!((level.total_quantity.raw(), level.hidden_quantity.raw()), (10, 2));
+    }
+
+    #[test]
+    fn iceberg_refresh_loses_time_priority() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 25).with_display_quantity(10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 10)).unwrap();
+
+        let trades = book.add_order(Order::new(3, Side::Buy, 100, 15)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.maker_order_id, t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(1, 10), (2, 5)]);
+
+        let level = &book.asks[&Price::new(100)];
+        let queue: Vec<_> = level.iter(&book.arena).map(|o| (o.order_id, o.quantity.raw())).collect();
+        assert_eq!(queue, vec![(2, 5), (1, 10)]);
+        assert_eq!((level.total_quantity.raw(), level.hidden_quantity.raw()), (15, 5));
+    }
+
+    #[test]
+    fn taker_sweeps_through_iceberg_reserve() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 25).with_display_quantity(10)).unwrap();
+
+        let fok = Order::new(2, Side::Sell, 100, 25).with_time_in_force(TimeInForce::Fok);
+        let trades = book.add_order(fok).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| t.quantity.raw()).collect();
+        assert_eq!(fills, vec![10, 10, 5]);
+        assert!(book.bids.is_empty());
+        assert_eq!(book.cancel_order_by_id(1), Err(BookError::UnknownOrder(1)));
+    }
+
+    #[test]
+    fn incoming_iceberg_takes_full_size_then_hides_remainder() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 30)).unwrap();
+
+        let trades = book.add_order(Order::new(2, Side::Buy, 101, 50).with_display_quantity(5)).unwrap();
+        assert_eq!(trades[0].quantity.raw(), 30);
+        let level = &book.bids[&Price::new(101)];
+        assert_eq!((level.total_quantity.raw(), level.hidden_quantity.raw()), (5, 15));
+
+        let market = Order::market(3, Side::Buy, 10).with_display_quantity(5);
+        assert_eq!(book.add_order(market), Err(BookError::IncompatibleInstructions(3)));
+    }
+
+    #[test]
+    fn stop_market_triggers_on_last_trade() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 105, 5)).unwrap();
+        assert!(book.add_order(Order::stop_market(3, Side::Buy, 100, 5)).unwrap().is_empty());
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 5);
+
+        let trades = book.add_order(Order::new(4, Side::Buy, 100, 2)).unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|t| (t.taker_order_id, t.maker_order_id, t.price.raw(), t.quantity.raw()))
+            .collect();
+        assert_eq!(fills, vec![(4, 1, 100, 2), (3, 1, 100, 3), (3, 2, 105, 2)]);
+        assert_eq!(book.last_trade_price(), Some(Price::new(105)));
+    }
+
+    #[test]
+    fn stop_limit_rests_at_limit_after_trigger() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 95, 5)).unwrap();
+        book.add_order(Order::stop_limit(3, Side::Sell, 100, 98, 8)).unwrap();
+
+        let trades = book.add_order(Order::new(4, Side::Sell, 100, 1)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.taker_order_id, t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(4, 1), (3, 4)]);
+        assert_eq!(book.asks[&Price::new(98)].total_quantity, 4);
+        assert_eq!(book.bids[&Price::new(95)].total_quantity, 5);
+    }
+
+    #[test]
+    fn stops_cascade_deterministically() {
+        let mut book = OrderBook::new();
+        for (id, price) in [(1, 100), (2, 101), (3, 102), (4, 103), (5, 104)] {
+            book.add_order(Order::new(id, Side::Sell, price, 1)).unwrap();
+        }
+        book.add_order(Order::stop_market(10, Side::Buy, 102, 1)).unwrap();
+        book.add_order(Order::stop_market(11, Side::Buy, 101, 1)).unwrap();
+        book.add_order(Order::stop_market(12, Side::Buy, 101, 1)).unwrap();
+
+        let trades = book.add_order(Order::new(20, Side::Buy, 101, 2)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.taker_order_id, t.price.raw())).collect();
+        assert_eq!(fills, vec![(20, 100), (20, 101), (11, 102), (12, 103), (10, 104)]);
+        assert!(book.asks.is_empty());
+    }
+
+    #[test]
+    fn pending_stops_can_be_cancelled() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::stop_market(1, Side::Sell, 90, 5)).unwrap();
+        assert_eq!(book.add_order(Order::new(1, Side::Buy, 100, 1)), Err(BookError::DuplicateOrderId(1)));
+        assert_eq!(book.cancel_order_by_id(1).unwrap().stop_price, 90);
+        assert_eq!(book.cancel_order_by_id(1), Err(BookError::UnknownOrder(1)));
+        assert_eq!(book.add_order(Order::stop_market(2, Side::Sell, 0, 5)), Err(BookError::InvalidPrice(Price::ZERO)));
+    }
+
+    #[test]
+    fn trailing_stop_follows_favourable_trades() {
+        let mut book = OrderBook::new();
+        let trailing = Order::trailing_stop(1, Side::Sell, TrailingOffset::Amount(5), 3);
+        assert_eq!(book.add_order(trailing), Err(BookError::NoReferencePrice(1)));
+
+        book.add_order(Order::new(2, Side::Sell, 100, 1)).unwrap();
+        book.add_order(Order::new(3, Side::Buy, 100, 1)).unwrap();
+        book.add_order(trailing).unwrap();
+        book.add_order(Order::new(4, Side::Buy, 90, 10)).unwrap();
+
+        for (id, price) in [(5, 110), (6, 107)] {
+            book.add_order(Order::new(id, Side::Sell, price, 1)).unwrap();
+            let trades = book.add_order(Order::new(id + 10, Side::Buy, price, 1)).unwrap();
+            assert_eq!(trades.len(), 1);
+        }
+        assert!(book.stops.contains(1));
+
+        book.add_order(Order::new(7, Side::Sell, 103, 1)).unwrap();
+        let trades = book.add_order(Order::new(17, Side::Buy, 103, 1)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.taker_order_id, t.price.raw(), t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(17, 103, 1), (1, 90, 3)]);
+    }
+
+    #[test]
+    fn trailing_offsets_beyond_the_price_range_are_rejected_or_saturate() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, i64::MAX / 4, 1)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, i64::MAX / 4, 1)).unwrap();
+        let offset = TrailingOffset::Amount(u64::MAX);
+        assert_eq!(
+            book.add_order(Order::trailing_stop(3, Side::Sell, offset, 1)),
+            Err(BookError::TrailingOffsetOutOfRange { order_id: 3, offset })
+        );
+        let huge = Order::trailing_stop(4, Side::Buy, TrailingOffset::BasisPoints(u64::MAX), 1);
+        book.add_order(huge).unwrap();
+        assert_eq!(book.stops.get(4).unwrap().stop_price, Price::MAX);
+    }
+
+    #[test]
+    fn trailing_stop_by_basis_points() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 1_000, 1)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 1_000, 1)).unwrap();
+
+        let trailing = Order::trailing_stop(3, Side::Buy, TrailingOffset::BasisPoints(100), 1);
+        book.add_order(trailing).unwrap();
+        book.add_order(Order::new(4, Side::Buy, 900, 1)).unwrap();
+        book.add_order(Order::new(5, Side::Sell, 900, 1)).unwrap();
+        book.add_order(Order::new(6, Side::Sell, 950, 5)).unwrap();
+
+        assert!(book.add_order(Order::new(7, Side::Buy, 909, 1)).unwrap().is_empty());
+        assert!(book.stops.contains(3));
+        book.add_order(Order::new(8, Side::Sell, 909, 1)).unwrap();
+        assert!(!book.stops.contains(3));
+        assert_eq!(book.asks[&Price::new(950)].total_quantity, 4);
+    }
+
+    #[test]
+    fn midpoint_peg_trades_at_half_tick() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 101, 10)).unwrap();
+        let mid = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(Order::pegged(3, Side::Sell, mid, 5)).unwrap();
+        assert_eq!(book.peg_price(3), Some(201));
+
+        let trades = book.add_order(Order::new(4, Side::Buy, 101, 7)).unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|t| (t.maker_order_id, t.price.raw(), t.half_tick, t.quantity.raw()))
+            .collect();
+        assert_eq!(fills, vec![(3, 100, true, 5), (2, 101, false, 2)]);
+    }
+
+    #[test]
+    fn half_tick_trades_elect_stops_on_their_exact_price() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 101, 10)).unwrap();
+        let mid = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(Order::pegged(3, Side::Sell, mid, 5)).unwrap();
+        book.add_order(Order::stop_market(4, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::stop_market(5, Side::Buy, 101, 5)).unwrap();
+
+        let trades = book.add_order(Order::market(6, Side::Buy, 1)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert!(book.stops.contains(4) && book.stops.contains(5));
+        assert_eq!(book.last_trade_price(), Some(Price::new(100)));
+
+        let trailing = Order::trailing_stop(7, Side::Sell, TrailingOffset::Amount(2), 5);
+        book.add_order(trailing).unwrap();
+        assert_eq!(book.stops.get(7).unwrap().stop_price, 98);
+    }
+
+    #[test]
+    fn primary_peg_follows_the_bbo() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 105, 10)).unwrap();
+        let primary = Peg { reference: PegReference::Primary, offset: -1 };
+        book.add_order(Order::pegged(3, Side::Buy, primary, 5)).unwrap();
+        assert_eq!(book.peg_price(3), Some(198));
+
+        book.add_order(Order::new(4, Side::Buy, 102, 10)).unwrap();
+        assert_eq!(book.peg_price(3), Some(202));
+        book.cancel_order_by_id(4).unwrap();
+        book.cancel_order_by_id(1).unwrap();
+        assert_eq!(book.peg_price(3), None);
+
+        let market = Peg { reference: PegReference::Market, offset: 0 };
+        book.add_order(Order::pegged(5, Side::Buy, market, 5)).unwrap();
+        assert_eq!(book.peg_price(5), Some(209));
+    }
+
+    #[test]
+    fn capped_pegs_at_one_price_fill_in_time_order() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 101, 10)).unwrap();
+        let near = Peg { reference: PegReference::Primary, offset: -1 };
+        let far = Peg { reference: PegReference::Primary, offset: -2 };
+        book.add_order(Order::pegged(3, Side::Sell, near, 5)).unwrap();
+        book.add_order(Order::pegged(4, Side::Sell, far, 5)).unwrap();
+        assert_eq!((book.peg_price(3), book.peg_price(4)), (Some(201), Some(201)));
+
+        let trades = book.add_order(Order::market(5, Side::Buy, 7)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.maker_order_id, t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(3, 5), (4, 2)]);
+    }
+
+    #[test]
+    fn crossing_pegs_trade_with_each_other() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 104, 10)).unwrap();
+        let mid = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(Order::pegged(3, Side::Buy, mid, 5)).unwrap();
+
+        let trades = book.add_order(Order::pegged(4, Side::Sell, mid, 8)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].maker_order_id, trades[0].price.raw(), trades[0].quantity.raw()), (3, 102, 5));
+        assert_eq!(book.peg_price(4), Some(204));
+
+        let primary = Peg { reference: PegReference::Primary, offset: 1 };
+        assert!(book.add_order(Order::pegged(5, Side::Buy, primary, 2)).unwrap().is_empty());
+        assert_eq!(book.peg_price(5), Some(202));
+
+        let trades = book.add_order(Order::new(6, Side::Sell, 102, 1)).unwrap();
+        let fills: Vec<_> = trades
+            .iter()
+            .map(|t| (t.taker_order_id, t.maker_order_id, t.price.raw(), t.quantity.raw()))
+            .collect();
+        assert_eq!(fills, vec![(5, 4, 101, 2)]);
+        assert_eq!(book.asks[&Price::new(102)].total_quantity, 1);
+    }
+
+    #[test]
+    fn pegs_reject_incompatible_instructions() {
+        let mut book = OrderBook::new();
+        let mid = Peg { reference: PegReference::Midpoint, offset: 0 };
+        let fok = Order::pegged(1, Side::Buy, mid, 5).with_time_in_force(TimeInForce::Fok);
+        assert_eq!(book.add_order(fok), Err(BookError::IncompatibleInstructions(1)));
+
+        book.add_order(Order::pegged(2, Side::Buy, mid, 5)).unwrap();
+        assert_eq!(book.peg_price(2), None);
+        book.modify_order_by_id(2, Qty::new(3)).unwrap();
+        assert_eq!(book.cancel_order_by_id(2).unwrap().quantity, 3);
+    }
+
+    #[test]
+    fn gtd_orders_expire_on_the_injected_clock() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut book = OrderBook::with_clock(clock.clone());
+        let gtd = |id, side, price, expiry| {
+            Order::new(id, side, price, 5).with_time_in_force(TimeInForce::Gtd(expiry))
+        };
+
+        assert_eq!(book.add_order(gtd(1, Side::Buy, 100, 1_000)), Err(BookError::AlreadyExpired(1)));
+        book.add_order(gtd(2, Side::Buy, 100, 2_000)).unwrap();
+        book.add_order(gtd(3, Side::Sell, 110, 1_500)).unwrap();
+        book.add_order(Order::stop_market(4, Side::Sell, 90, 5).with_time_in_force(TimeInForce::Gtd(1_500)))
+            .unwrap();
+        assert_eq!(book.bids[&Price::new(100)].iter(&book.arena).next().unwrap().timestamp, 1_000);
+
+        clock.advance(500);
+        let expired: Vec<_> = book.expire_orders().iter().map(|o| o.order_id).collect();
+        assert_eq!(expired, vec![3, 4]);
+        assert!(book.asks.is_empty());
+
+        clock.set(2_000);
+        let trades = book.add_order(Order::new(5, Side::Sell, 100, 5)).unwrap();
+        assert!(trades.is_empty());
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn gtd_expiry_skips_orders_that_already_left() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut book = OrderBook::with_clock(clock.clone());
+        let gtd = Order::new(1, Side::Buy, 100, 5).with_time_in_force(TimeInForce::Gtd(10));
+        book.add_order(gtd).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 5)).unwrap();
+        book.add_order(Order::new(1, Side::Buy, 99, 5)).unwrap();
+
+        clock.set(10);
+        assert!(book.expire_orders().is_empty());
+        assert_eq!(book.bids[&Price::new(99)].total_quantity, 5);
+    }
+
+    #[test]
+    fn parked_stops_can_be_modified_before_election() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::stop_market(1, Side::Sell, 95, 5)).unwrap();
+        book.add_order(Order::stop_market(2, Side::Sell, 95, 5)).unwrap();
+        book.modify_order_by_id(1, Qty::new(2)).unwrap();
+        assert_eq!(book.stops.get(1).unwrap().quantity.raw(), 2);
+
+        book.add_order(Order::new(3, Side::Buy, 95, 10)).unwrap();
+        let trades = book.add_order(Order::new(4, Side::Sell, 95, 1)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.taker_order_id, t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(4, 1), (1, 2), (2, 5)]);
+    }
+
+    #[test]
+    fn expired_orders_cannot_be_modified_or_cancelled() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut book = OrderBook::with_clock(clock.clone());
+        book.add_order(Order::new(1, Side::Buy, 100, 5).with_time_in_force(TimeInForce::Gtd(10))).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 99, 5).with_time_in_force(TimeInForce::Gtd(20))).unwrap();
+
+        clock.set(10);
+        assert_eq!(book.modify_order_by_id(1, Qty::new(8)), Err(BookError::UnknownOrder(1)));
+        assert!(!book.bids.contains_key(&Price::new(100)));
+        let expired: Vec<_> = book.drain_cancels().iter().map(|c| (c.order_id, c.reason)).collect();
+        assert_eq!(expired, vec![(1, CancelReason::Expired)]);
+
+        clock.set(20);
+        assert_eq!(book.cancel_order_by_id(2).map(|o| o.order_id), Err(BookError::UnknownOrder(2)));
+        assert_eq!(book.drain_cancels()[0].reason, CancelReason::Expired);
+    }
+
+    fn self_trade_book() -> OrderBook {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Sell, 100, 5).with_account(7)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 5).with_account(8)).unwrap();
+        book.drain_cancels();
+        book
+    }
+
+    fn cancels(book: &mut OrderBook) -> Vec<(u64, u64, CancelReason)> {
+        book.drain_cancels()
+            .iter()
+            .map(|c| (c.order_id, c.quantity.raw(), c.reason))
+            .collect()
+    }
+
+    const STP: CancelReason = CancelReason::SelfTradePrevention;
+
+    #[test]
+    fn stp_cancel_newest() {
+        let mut book = self_trade_book();
+        let taker = Order::new(3, Side::Buy, 100, 8)
+            .with_account(7)
+            .with_self_trade_prevention(SelfTradePrevention::CancelNewest);
+        assert!(book.add_order(taker).unwrap().is_empty());
+        assert_eq!(cancels(&mut book), vec![(3, 8, STP)]);
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 10);
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn stp_cancel_oldest() {
+        let mut book = self_trade_book();
+        let taker = Order::new(3, Side::Buy, 100, 8)
+            .with_account(7)
+            .with_self_trade_prevention(SelfTradePrevention::CancelOldest);
+        let trades = book.add_order(taker).unwrap();
+        assert_eq!((trades[0].maker_order_id, trades[0].quantity.raw()), (2, 5));
+        assert_eq!(cancels(&mut book), vec![(1, 5, STP)]);
+        assert_eq!(book.bids[&Price::new(100)].total_quantity, 3);
+    }
+
+    #[test]
+    fn stp_cancel_both() {
+        let mut book = self_trade_book();
+        let taker = Order::new(3, Side::Buy, 100, 8)
+            .with_account(7)
+            .with_self_trade_prevention(SelfTradePrevention::CancelBoth);
+        assert!(book.add_order(taker).unwrap().is_empty());
+        assert_eq!(cancels(&mut book), vec![(1, 5, STP), (3, 8, STP)]);
+        let ids: Vec<_> = book.asks[&Price::new(100)].iter(&book.arena).map(|o| o.order_id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[test]
+    fn stp_decrement_and_cancel() {
+        let mut book = self_trade_book();
+        let small = Order::new(3, Side::Buy, 100, 2)
+            .with_account(7)
+            .with_self_trade_prevention(SelfTradePrevention::DecrementAndCancel);
+        assert!(book.add_order(small).unwrap().is_empty());
+        assert_eq!(cancels(&mut book), vec![(1, 2, STP), (3, 2, STP)]);
+        assert_eq!(book.asks[&Price::new(100)].iter(&book.arena).next().unwrap().quantity, 3);
+
+        let large = Order::new(4, Side::Buy, 100, 6)
+            .with_account(7)
+            .with_self_trade_prevention(SelfTradePrevention::DecrementAndCancel);
+        let trades = book.add_order(large).unwrap();
+        assert_eq!((trades[0].maker_order_id, trades[0].quantity.raw()), (2, 3));
+        assert_eq!(cancels(&mut book), vec![(1, 3, STP), (4, 3, STP)]);
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 2);
+    }
+
+    #[test]
+    fn cancels_record_their_reason() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 5)).unwrap();
+        book.cancel_order_by_id(1).unwrap();
+        book.add_order(Order::market(2, Side::Buy, 4)).unwrap();
+        book.add_order(Order::new(3, Side::Buy, 100, 5).with_time_in_force(TimeInForce::Day)).unwrap();
+        book.expire_day_orders();
+        assert_eq!(
+            cancels(&mut book),
+            vec![(1, 5, CancelReason::Requested), (2, 4, CancelReason::Unfilled), (3, 5, CancelReason::Expired)]
+        );
+    }
+
+    #[test]
+    fn pro_rata_book_shares_a_level_and_reports_fills_in_time_priority() {
+        let policy = ProRata { min_allocation: 0, remainder: Remainder::TimePriority };
+        let mut book = OrderBook::new().with_allocation_policy(Arc::new(policy));
+        book.add_order(Order::new(1, Side::Sell, 100, 10)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 30)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 100, 60)).unwrap();
+
+        let trades = book.add_order(Order::new(4, Side::Buy, 100, 11)).unwrap();
+        let fills: Vec<(u64, u64)> = trades.iter().map(|t| (t.maker_order_id, t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(1, 2), (2, 3), (3, 6)]);
+        assert_eq!(book.asks[&Price::new(100)].total_quantity, 89);
+
+        let trades = book.add_order(Order::new(5, Side::Buy, 101, 95)).unwrap();
+        assert_eq!(trades.iter().map(|t| t.quantity.raw()).sum::<u64>(), 89);
+        assert!(book.asks.is_empty());
+        assert_eq!(book.bids[&Price::new(101)].total_quantity, 6);
+    }
+
+    #[test]
+    fn auction_accumulates_crossed_orders_and_publishes_indicative_price() {
+        let mut book = OrderBook::new();
+        book.start_auction(Some(Price::new(100)));
+        assert!(book.add_order(Order::new(1, Side::Buy, 102, 10)).unwrap().is_empty());
+        assert_eq!(book.indicative_uncross(), None);
+        assert!(book.add_order(Order::new(2, Side::Sell, 99, 6)).unwrap().is_empty());
+        assert_eq!(
+            book.indicative_uncross(),
+            Some(AuctionQuote { price: Price::new(99), matched_quantity: Qty::new(6), imbalance: 4 })
+        );
+        book.add_order(Order::new(3, Side::Sell, 101, 4)).unwrap();
+        assert_eq!(
+            book.indicative_uncross(),
+            Some(AuctionQuote { price: Price::new(101), matched_quantity: Qty::new(10), imbalance: 0 })
+        );
+        assert_eq!(book.bids[&Price::new(102)].total_quantity, 10);
+        assert_eq!(book.asks[&Price::new(99)].total_quantity, 6);
+
+        assert_eq!(
+            book.add_order(Order::market(4, Side::Buy, 1)),
+            Err(BookError::NotAcceptedInAuction(4))
+        );
+        assert_eq!(
+            book.add_order(Order::new(5, Side::Buy, 100, 1).with_time_in_force(TimeInForce::Ioc)),
+            Err(BookError::NotAcceptedInAuction(5))
+        );
+    }
+
+    #[test]
+    fn uncross_executes_at_one_price_and_resumes_continuous_trading() {
+        let mut book = OrderBook::new();
+        book.start_auction(Some(Price::new(101)));
+        book.add_order(Order::new(1, Side::Buy, 102, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 10)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 99, 8)).unwrap();
+        book.add_order(Order::new(4, Side::Sell, 101, 10)).unwrap();
+        let quote = book.indicative_uncross().unwrap();
+        assert_eq!((quote.price.raw(), quote.matched_quantity.raw()), (100, 8));
+
+        let trades = book.uncross();
+        let fills: Vec<(u64, u64, u64, i64)> = trades
+            .iter()
+            .map(|t| (t.taker_order_id, t.maker_order_id, t.quantity.raw(), t.price.raw()))
+            .collect();
+        assert_eq!(fills, vec![(3, 1, 5, 100), (3, 2, 3, 100)]);
+        assert!(!book.is_in_auction());
+        assert_eq!(book.last_trade_price(), Some(Price::new(100)));
+        assert_eq!(book.bids[&Price::new(100)].total_quantity, 7);
+        assert!(!book.asks.contains_key(&Price::new(99)));
+
+        let trades = book.add_order(Order::new(5, Side::Sell, 100, 2)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].maker_order_id, 2);
+    }
+
+    #[test]
+    fn uncross_fills_hidden_iceberg_quantity() {
+        let mut book = OrderBook::new();
+        book.start_auction(None);
+        book.add_order(Order::new(1, Side::Sell, 100, 10).with_display_quantity(2)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 7)).unwrap();
+        let quote = book.indicative_uncross().unwrap();
+        assert_eq!(quote.matched_quantity.raw(), 7);
+
+        let trades = book.uncross();
+        assert_eq!(trades.iter().map(|t| t.quantity.raw()).sum::<u64>(), 7);
+        let level = &book.asks[&Price::new(100)];
+        assert_eq!((level.total_quantity.raw(), level.hidden_quantity.raw()), (2, 1));
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn halted_session_accepts_only_cancels() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 100, 10)).unwrap();
+        book.set_session_state(SessionState::Halted).unwrap();
+
+        assert_eq!(
+            book.add_order(Order::new(2, Side::Sell, 100, 5)),
+            Err(BookError::SessionRejected { state: SessionState::Halted, operation: Operation::AddOrder })
+        );
+        assert_eq!(
+            book.modify_order_by_id(1, Qty::new(5)),
+            Err(BookError::SessionRejected { state: SessionState::Halted, operation: Operation::ModifyOrder })
+        );
+        assert!(book.cancel_order_by_id(1).is_ok());
+        assert_eq!(
+            book.set_session_state(SessionState::Halted),
+            Err(BookError::InvalidTransition { from: SessionState::Halted, to: SessionState::Halted })
+        );
+        assert_eq!(
+            BookError::SessionRejected { state: SessionState::Halted, operation: Operation::AddOrder }.to_string(),
+            "cannot add orders while the session is halted"
+        );
+    }
+
+    #[test]
+    fn pre_open_collects_orders_and_opening_uncrosses() {
+        let mut book = OrderBook::new();
+        book.set_session_state(SessionState::Closed).unwrap();
+        book.set_session_state(SessionState::PreOpen).unwrap();
+        assert!(book.add_order(Order::new(1, Side::Buy, 101, 10)).unwrap().is_empty());
+        assert!(book.add_order(Order::new(2, Side::Sell, 100, 4)).unwrap().is_empty());
+
+        let trades = book.set_session_state(SessionState::Continuous).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].quantity.raw(), 4);
+        assert!(!book.is_in_auction());
+        assert_eq!(book.add_order(Order::new(3, Side::Sell, 101, 6)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn due_transitions_apply_before_adds_cancels_and_modifies() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut book = OrderBook::with_clock(clock.clone());
+        book.set_session_state(SessionState::Closed).unwrap();
+        book.set_session_state(SessionState::PreOpen).unwrap();
+        book.add_order(Order::new(1, Side::Buy, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 100, 3)).unwrap();
+        book.schedule_session_state(100, SessionState::Continuous);
+        book.schedule_session_state(200, SessionState::Closed);
+        book.schedule_session_state(200, SessionState::PreOpen);
+
+        clock.set(100);
+        book.modify_order_by_id(1, Qty::new(1)).unwrap();
+        assert_eq!(book.session_state(), SessionState::Continuous);
+        assert_eq!(book.bids[&Price::new(100)].total_quantity, 1);
+
+        clock.set(200);
+        assert_eq!(
+            book.add_order(Order::new(3, Side::Sell, 100, 1).with_time_in_force(TimeInForce::Ioc)),
+            Err(BookError::NotAcceptedInAuction(3))
+        );
+        assert_eq!(book.session_state(), SessionState::PreOpen);
+        let trades = book.poll_session();
+        assert_eq!((trades.len(), trades[0].quantity.raw()), (1, 3));
+        assert!(book.poll_session().is_empty());
+    }
+
+    #[test]
+    fn close_expires_day_stops_and_pegs() {
+        let mut book = OrderBook::new();
+        let day = |order: Order| order.with_time_in_force(TimeInForce::Day);
+        book.add_order(Order::new(1, Side::Buy, 100, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 102, 5)).unwrap();
+        book.add_order(day(Order::stop_market(3, Side::Buy, 105, 5))).unwrap();
+        let midpoint = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(day(Order::pegged(4, Side::Buy, midpoint, 5))).unwrap();
+        book.drain_cancels();
+
+        book.set_session_state(SessionState::Closed).unwrap();
+        assert_eq!(book.order_count(), 2);
+        let expired: Vec<_> = book.drain_cancels().iter().map(|c| (c.order_id, c.reason)).collect();
+        assert_eq!(expired, vec![(3, CancelReason::Expired), (4, CancelReason::Expired)]);
+    }
+
+    #[test]
+    fn scheduled_transitions_follow_the_clock() {
+        let clock = Arc::new(ManualClock::new(0));
+        let mut book = OrderBook::with_clock(clock.clone());
+        book.add_order(Order::new(1, Side::Buy, 100, 10).with_time_in_force(TimeInForce::Day)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 99, 10)).unwrap();
+        book.schedule_session_state(100, SessionState::Halted);
+        book.schedule_session_state(200, SessionState::Continuous);
+        book.schedule_session_state(300, SessionState::Closed);
+
+        clock.set(150);
+        book.poll_session();
+        assert_eq!(book.session_state(), SessionState::Halted);
+        clock.set(250);
+        book.poll_session();
+        assert_eq!(book.session_state(), SessionState::Continuous);
+        clock.set(300);
+        book.poll_session();
+        assert_eq!(book.session_state(), SessionState::Closed);
+
+        assert!(!book.bids.contains_key(&Price::new(100)));
+        assert_eq!(book.bids[&Price::new(99)].total_quantity, 10);
+        assert_eq!(
+            book.cancel_order_by_id(2),
+            Err(BookError::SessionRejected { state: SessionState::Closed, operation: Operation::CancelOrder })
+        );
+    }
+
+    #[test]
+    fn static_band_rejects_fat_finger_prices() {
+        let bands = PriceBands {
+            static_band: Some(BandWidth::Ticks(5)),
+            reference_price: Some(Price::new(100)),
+            volatility: None,
+        };
+        let mut book = OrderBook::new().with_price_bands(bands).unwrap();
+        assert_eq!(
+            book.add_order(Order::new(1, Side::Buy, 106, 10)),
+            Err(BookError::OutsidePriceBand {
+                order_id: 1,
+                price: Price::new(106),
+                low: Price::new(95),
+                high: Price::new(105)
+            })
+        );
+        assert!(book.add_order(Order::new(2, Side::Buy, 105, 10)).is_ok());
+        assert!(book.add_order(Order::market(3, Side::Sell, 1)).is_ok());
+
+        let wide = PriceBands { static_band: Some(BandWidth::Ticks(1 << 62)), ..bands };
+        assert_eq!(
+            OrderBook::new().with_price_bands(wide).err(),
+            Some(BookError::PriceBandTooWide(BandWidth::Ticks(1 << 62)))
+        );
+    }
+
+    fn volatile_book(action: InterruptionAction, clock: Arc<ManualClock>) -> OrderBook {
+        let bands = PriceBands {
+            volatility: Some(VolatilityInterruption { width: BandWidth::BasisPoints(500), action }),
+            ..PriceBands::default()
+        };
+        let mut book = OrderBook::with_clock(clock).with_price_bands(bands).unwrap();
+        book.add_order(Order::new(1, Side::Sell, 100, 1)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 1)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 103, 5)).unwrap();
+        book.add_order(Order::new(4, Side::Sell, 110, 5)).unwrap();
+        book
+    }
+
+    #[test]
+    fn volatility_halt_stops_the_stop_cascade() {
+        let bands = PriceBands {
+            volatility: Some(VolatilityInterruption {
+                width: BandWidth::BasisPoints(500),
+                action: InterruptionAction::Halt,
+            }),
+            ..PriceBands::default()
+        };
+        let mut book = OrderBook::new().with_price_bands(bands).unwrap();
+        book.add_order(Order::new(1, Side::Sell, 100, 1)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 100, 1)).unwrap();
+        book.add_order(Order::new(3, Side::Sell, 101, 1)).unwrap();
+        book.add_order(Order::new(4, Side::Sell, 104, 3)).unwrap();
+        book.add_order(Order::new(5, Side::Sell, 107, 10)).unwrap();
+        book.add_order(Order::stop_market(6, Side::Buy, 101, 4)).unwrap();
+        book.add_order(Order::stop_market(7, Side::Buy, 101, 5)).unwrap();
+
+        let trades = book.add_order(Order::new(8, Side::Buy, 101, 1)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.taker_order_id, t.price.raw(), t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(8, 101, 1), (6, 104, 3)]);
+        assert_eq!(book.session_state(), SessionState::Halted);
+        assert_eq!(book.asks[&Price::new(107)].total_quantity, 10);
+        assert!(book.stops.contains(7));
+    }
+
+    #[test]
+    fn volatility_interruption_halts_before_trading_outside_band() {
+        let mut book = volatile_book(InterruptionAction::Halt, Arc::new(ManualClock::new(0)));
+        let trades = book.add_order(Order::market(5, Side::Buy, 10)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].price.raw(), trades[0].quantity.raw()), (103, 5));
+        assert_eq!(book.session_state(), SessionState::Halted);
+        assert_eq!(book.asks[&Price::new(110)].total_quantity, 5);
+        assert_eq!(cancels(&mut book), vec![(5, 5, CancelReason::VolatilityInterruption)]);
+    }
+
+    #[test]
+    fn volatility_interruption_can_start_a_short_auction() {
+        let clock = Arc::new(ManualClock::new(0));
+        let action = InterruptionAction::Auction { duration: 1_000 };
+        let mut book = volatile_book(action, clock.clone());
+        let trades = book.add_order(Order::new(5, Side::Buy, 110, 10)).unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(book.session_state(), SessionState::PreOpen);
+        let quote = book.indicative_uncross().unwrap();
+        assert_eq!((quote.price.raw(), quote.matched_quantity.raw()), (110, 5));
+
+        clock.advance(999);
+        assert!(book.poll_session().is_empty());
+        clock.advance(1);
+        let trades = book.poll_session();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].price.raw(), trades[0].quantity.raw()), (110, 5));
+        assert_eq!(book.session_state(), SessionState::Continuous);
+    }
+
+    #[test]
+    fn fill_or_kill_counts_only_liquidity_inside_volatility_band() {
+        let mut book = volatile_book(InterruptionAction::Halt, Arc::new(ManualClock::new(0)));
+        let order = Order::new(5, Side::Buy, 110, 10).with_time_in_force(TimeInForce::Fok);
+        assert!(book.add_order(order).unwrap().is_empty());
+        assert_eq!(book.session_state(), SessionState::Continuous);
+        assert_eq!(cancels(&mut book), vec![(5, 10, CancelReason::Unfilled)]);
+    }
+
+    fn futures_book() -> OrderBook {
+        let instrument = Instrument {
+            tick_size: Price::new(25),
+            lot_size: Qty::new(2),
+            min_quantity: Qty::new(2),
+            max_quantity: Some(Qty::new(100)),
+            max_notional: Some(500_000),
+            ..Instrument::new("ESZ4")
+        };
+        OrderBook::new().with_instrument(instrument).unwrap()
+    }
+
+    #[test]
+    fn instrument_rejects_off_tick_prices_and_bad_sizes() {
+        let mut book = futures_book();
+        assert_eq!(
+            book.add_order(Order::new(1, Side::Buy, 5010, 2)),
+            Err(BookError::PriceNotOnTick { order_id: 1, price: Price::new(5010), tick_size: Price::new(25) })
+        );
+        assert_eq!(
+            book.add_order(Order::new(2, Side::Buy, 5000, 3)),
+            Err(BookError::QuantityNotOnLot { order_id: 2, quantity: Qty::new(3), lot_size: Qty::new(2) })
+        );
+        assert_eq!(
+            book.add_order(Order::new(3, Side::Buy, 5000, 102)),
+            Err(BookError::QuantityOutOfRange {
+                order_id: 3,
+                quantity: Qty::new(102),
+                min: Qty::new(2),
+                max: Qty::new(100)
+            })
+        );
+        assert_eq!(
+            book.add_order(Order::new(4, Side::Buy, 5025, 100)),
+            Err(BookError::NotionalTooLarge { order_id: 4, notional: 502_500, max: 500_000 })
+        );
+        assert_eq!(
+            book.add_order(Order::stop_market(5, Side::Sell, 4990, 2)),
+            Err(BookError::PriceNotOnTick { order_id: 5, price: Price::new(4990), tick_size: Price::new(25) })
+        );
+        assert!(book.add_order(Order::new(6, Side::Buy, 5000, 100)).is_ok());
+        assert!(book.add_order(Order::market(7, Side::Sell, 4)).is_ok());
+    }
+
+    #[test]
+    fn instrument_values_market_and_pegged_orders_for_notional() {
+        let mut book = futures_book();
+        assert_eq!(book.add_order(Order::market(1, Side::Buy, 2)), Err(BookError::NoReferencePrice(1)));
+        book.add_order(Order::new(2, Side::Sell, 5025, 10)).unwrap();
+        assert_eq!(
+            book.add_order(Order::market(3, Side::Buy, 100)),
+            Err(BookError::NotionalTooLarge { order_id: 3, notional: 502_500, max: 500_000 })
+        );
+        let peg = Peg { reference: PegReference::Market, offset: 0 };
+        assert_eq!(
+            book.add_order(Order::pegged(4, Side::Buy, peg, 100)),
+            Err(BookError::NotionalTooLarge { order_id: 4, notional: 502_500, max: 500_000 })
+        );
+        book.add_order(Order::pegged(5, Side::Buy, peg, 2)).unwrap();
+        assert_eq!(
+            book.modify_order_by_id(5, Qty::new(100)),
+            Err(BookError::NotionalTooLarge { order_id: 5, notional: 502_500, max: 500_000 })
+        );
+    }
+
+    #[test]
+    fn invalid_instruments_and_bands_are_rejected_when_set() {
+        let zero_tick = Instrument { tick_size: Price::ZERO, ..Instrument::new("ESZ4") };
+        assert_eq!(OrderBook::new().with_instrument(zero_tick).err(), Some(BookError::InvalidInstrument));
+
+        let bands = PriceBands { static_band: Some(BandWidth::Ticks(1 << 60)), ..PriceBands::default() };
+        let coarse = Instrument { tick_size: Price::new(4), ..Instrument::new("ESZ4") };
+        let book = OrderBook::new().with_price_bands(bands).unwrap();
+        assert_eq!(book.with_instrument(coarse).err(), Some(BookError::PriceBandTooWide(BandWidth::Ticks(1 << 60))));
+    }
+
+    #[test]
+    fn instrument_checks_modified_quantity() {
+        let mut book = futures_book();
+        book.add_order(Order::new(1, Side::Buy, 5000, 10)).unwrap();
+        assert_eq!(
+            book.modify_order_by_id(1, Qty::new(7)),
+            Err(BookError::QuantityNotOnLot { order_id: 1, quantity: Qty::new(7), lot_size: Qty::new(2) })
+        );
+        assert_eq!(book.modify_order_by_id(99, Qty::new(2)), Err(BookError::UnknownOrder(99)));
+        assert!(book.modify_order_by_id(1, Qty::new(4)).is_ok());
+        assert_eq!(book.bids[&Price::new(5000)].total_quantity, 4);
+    }
+
+    #[test]
+    fn post_only_does_not_take_from_pegged_orders() {
+        let mut book = OrderBook::new();
+        book.add_order(Order::new(1, Side::Buy, 99, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, 102, 5)).unwrap();
+        let midpoint = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(Order::pegged(3, Side::Buy, midpoint, 5)).unwrap();
+
+        let crossing = Order::new(4, Side::Sell, 100, 5).with_post_only(PostOnly::Reject);
+        assert_eq!(book.add_order(crossing), Err(BookError::PostOnlyWouldCross(4)));
+        let sliding = Order::new(5, Side::Sell, 100, 5).with_post_only(PostOnly::Slide);
+        assert!(book.add_order(sliding).unwrap().is_empty());
+        assert_eq!(book.asks[&Price::new(101)].total_quantity, 5);
+        assert_eq!(book.pegs.get(3).unwrap().quantity.raw(), 5);
+    }
+
+    #[test]
+    fn post_only_slides_by_instrument_tick() {
+        let mut book = futures_book();
+        book.add_order(Order::new(1, Side::Sell, 5000, 2)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 5000, 2).with_post_only(PostOnly::Slide)).unwrap();
+        assert_eq!(book.bids[&Price::new(4975)].total_quantity, 2);
+    }
+
+    fn spread_book() -> OrderBook {
+        let spread = Instrument { allow_negative_prices: true, ..Instrument::new("CLZ4-CLF5") };
+        OrderBook::with_clock(Arc::new(ManualClock::new(1))).with_instrument(spread).unwrap()
+    }
+
+    #[test]
+    fn books_straddling_zero_keep_price_priority() {
+        let mut book = spread_book();
+        for (id, price) in [(1, -3), (2, 0), (3, 2)] {
+            book.add_order(Order::new(id, Side::Buy, price, 5)).unwrap();
+        }
+        book.add_order(Order::new(4, Side::Sell, 4, 5)).unwrap();
+        let prices: Vec<i64> = book.bids.keys().map(|price| price.raw()).collect();
+        assert_eq!(prices, vec![-3, 0, 2]);
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(Price::new(2)), Some(Price::new(4))));
+
+        let trades = book.add_order(Order::new(5, Side::Sell, -3, 12)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.maker_order_id, t.price.raw(), t.quantity.raw())).collect();
+        assert_eq!(fills, vec![(3, 2, 5), (2, 0, 5), (1, -3, 2)]);
+        assert_eq!(book.last_trade_price(), Some(Price::new(-3)));
+
+        book.add_order(Order::stop_market(6, Side::Buy, -1, 1)).unwrap();
+        book.add_order(Order::new(7, Side::Buy, -1, 1)).unwrap();
+        let trades = book.add_order(Order::new(8, Side::Sell, -1, 2)).unwrap();
+        let fills: Vec<_> = trades.iter().map(|t| (t.taker_order_id, t.maker_order_id, t.price.raw())).collect();
+        assert_eq!(fills, vec![(8, 7, -1), (6, 8, -1)]);
+    }
+
+    #[test]
+    fn prices_and_offsets_beyond_half_ticks_are_rejected() {
+        let mut book = OrderBook::new();
+        let huge = Price::new(i64::MAX / 2 + 1);
+        assert_eq!(book.add_order(Order::new(1, Side::Buy, huge.raw(), 5)), Err(BookError::InvalidPrice(huge)));
+        assert_eq!(
+            book.add_order(Order::stop_market(2, Side::Buy, huge.raw(), 5)),
+            Err(BookError::InvalidPrice(huge))
+        );
+        let far = Peg { reference: PegReference::Primary, offset: i64::MAX };
+        assert_eq!(
+            book.add_order(Order::pegged(3, Side::Buy, far, 5)),
+            Err(BookError::PegOffsetOutOfRange { order_id: 3, offset: i64::MAX })
+        );
+
+        book.add_order(Order::new(4, Side::Buy, i64::MAX / 2 - 2, 5)).unwrap();
+        book.add_order(Order::new(5, Side::Sell, i64::MAX / 2, 5)).unwrap();
+        let top = Peg { reference: PegReference::Primary, offset: i64::MAX / 2 };
+        book.add_order(Order::pegged(6, Side::Buy, top, 5)).unwrap();
+        assert_eq!(book.peg_price(6), Some(i64::MAX - 2));
+        let trades = book.add_order(Order::new(7, Side::Buy, i64::MAX / 2, 5)).unwrap();
+        assert_eq!(trades[0].maker_order_id, 5);
+    }
+
+    #[test]
+    fn non_positive_prices_need_an_instrument_that_allows_them() {
+        let mut book = OrderBook::new();
+        assert_eq!(
+            book.add_order(Order::new(1, Side::Buy, -1, 5)),
+            Err(BookError::InvalidPrice(Price::new(-1)))
+        );
+
+        let mut book = spread_book();
+        book.add_order(Order::new(1, Side::Sell, 0, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Buy, 1, 5).with_post_only(PostOnly::Slide)).unwrap();
+        assert_eq!(book.best_bid(), Some(Price::new(-1)));
+    }
+
+    #[test]
+    fn midpoint_pegs_trade_half_a_tick_below_zero() {
+        let mut book = spread_book();
+        book.add_order(Order::new(1, Side::Buy, -4, 5)).unwrap();
+        book.add_order(Order::new(2, Side::Sell, -1, 5)).unwrap();
+        let mid = Peg { reference: PegReference::Midpoint, offset: 0 };
+        book.add_order(Order::pegged(3, Side::Buy, mid, 5)).unwrap();
+        assert_eq!(book.peg_price(3), Some(-5));
+
+        let trades = book.add_order(Order::market(4, Side::Sell, 1)).unwrap();
+        assert_eq!((trades[0].maker_order_id, trades[0].price.raw(), trades[0].half_tick), (3, -3, true));
+    }
+}
@@ -0,0 +1,195 @@
+//! Pegged orders, repriced from the lit top of book.
+//!
+//! Pegged orders do not sit in the integer-keyed price levels: their price
+//! is derived on demand from the current best bid/ask, so it follows the
+//! BBO without being re-inserted. Prices here are in half-ticks so that
+//! midpoint pegs can sit (and trade) between two tick prices.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::order::{Order, Side};
+use crate::units::{Price, Qty};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PegReference {
+    /// Same-side best price: best bid for buys, best ask for sells.
+    Primary,
+    /// Opposite-side best price: best ask for buys, best bid for sells.
+    Market,
+    /// Halfway between the best bid and best ask.
+    Midpoint,
+}
+
+/// `price` in half-ticks, saturating at the ends of the range. Exact for
+/// every price the book accepts; see `in_half_tick_range`.
+pub fn half_ticks(price: Price) -> i64 {
+    price.raw().saturating_mul(2)
+}
+
+/// Whether `price` can be written exactly in half-ticks.
+pub fn in_half_tick_range(price: Price) -> bool {
+    price.raw().checked_mul(2).is_some()
+}
+
+/// Peg instruction: the order's price is `reference + offset` ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Peg {
+    pub reference: PegReference,
+    pub offset: i64,
+}
+
+/// Best lit bid and ask in ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TopOfBook {
+    pub bid: Option<Price>,
+    pub ask: Option<Price>,
+    /// Whether pegs may be priced at or below zero.
+    pub allow_negative_prices: bool,
+}
+
+impl Peg {
+    /// Price in half-ticks against `top`, or `None` while the reference is
+    /// missing or the price would not be positive in a book that requires
+    /// it. Buys are capped half a tick below the best ask and sells
+    /// half a tick above the best bid, so a peg never locks or crosses the
+    /// lit book.
+    pub fn price(&self, side: Side, top: TopOfBook) -> Option<i64> {
+        let (bid, ask) = (top.bid.map(half_ticks), top.ask.map(half_ticks));
+        let reference = match (self.reference, side) {
+            (PegReference::Primary, Side::Buy) | (PegReference::Market, Side::Sell) => bid?,
+            (PegReference::Primary, Side::Sell) | (PegReference::Market, Side::Buy) => ask?,
+            (PegReference::Midpoint, _) => top.bid?.raw().saturating_add(top.ask?.raw()),
+        };
+        let mut price = reference.saturating_add(self.offset.saturating_mul(2));
+        match side {
+            Side::Buy => {
+                if let Some(ask) = ask {
+                    price = price.min(ask.saturating_sub(1));
+                }
+            }
+            Side::Sell => {
+                if let Some(bid) = bid {
+                    price = price.max(bid.saturating_add(1));
+                }
+            }
+        }
+        (top.allow_negative_prices || price > 0).then_some(price)
+    }
+}
+
+type PegKey = (PegReference, i64);
+
+#[derive(Default)]
+pub struct PegBook {
+    bids: BTreeMap<PegKey, VecDeque<Order>>,
+    asks: BTreeMap<PegKey, VecDeque<Order>>,
+    index: HashMap<u64, (Side, PegKey)>,
+}
+
+impl PegBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, order_id: u64) -> bool {
+        self.index.contains_key(&order_id)
+    }
+
+    pub fn get(&self, order_id: u64) -> Option<&Order> {
+        let (side, key) = self.index.get(&order_id)?;
+        self.side(*side)[key].iter().find(|o| o.order_id == order_id)
+    }
+
+    pub fn insert(&mut self, order: Order) {
+        let peg = order.peg.expect("pegged order");
+        let key = (peg.reference, peg.offset);
+        self.index.insert(order.order_id, (order.side, key));
+        self.side_mut(order.side).entry(key).or_default().push_back(order);
+    }
+
+    pub fn remove(&mut self, order_id: u64) -> Option<Order> {
+        let (side, key) = self.index.remove(&order_id)?;
+        let pegs = self.side_mut(side);
+        let queue = pegs.get_mut(&key)?;
+        let position = queue.iter().position(|o| o.order_id == order_id)?;
+        let order = queue.remove(position);
+        if queue.is_empty() {
+            pegs.remove(&key);
+        }
+        order
+    }
+
+    pub fn set_quantity(&mut self, order_id: u64, quantity: Qty) -> bool {
+        let Some(&(side, key)) = self.index.get(&order_id) else { return false };
+        let queue = self.side_mut(side).get_mut(&key).expect("indexed peg");
+        let order = queue.iter_mut().find(|o| o.order_id == order_id).expect("indexed peg");
+        order.quantity = quantity;
+        true
+    }
+
+    /// Every pegged order on `side`, in no particular order.
+    pub fn orders(&self, side: Side) -> impl Iterator<Item = &Order> + '_ {
+        self.side(side).values().flatten()
+    }
+
+    /// The pegged order on `side` with the best price against `top`, as
+    /// `(price in half-ticks, order)`. Ties go to the earlier order, also
+    /// between offsets the BBO cap has moved onto the same price.
+    pub fn best(&self, side: Side, top: TopOfBook) -> Option<(i64, &Order)> {
+        let rank = |price: i64, order: &Order| {
+            let price = match side {
+                Side::Buy => -price,
+                Side::Sell => price,
+            };
+            (price, order.timestamp, order.order_id)
+        };
+        let pegs = self.side(side);
+        let mut best: Option<(i64, &Order)> = None;
+        for reference in [PegReference::Primary, PegReference::Market, PegReference::Midpoint] {
+            let range = pegs.range((reference, i64::MIN)..=(reference, i64::MAX));
+            let queues: Box<dyn Iterator<Item = &VecDeque<Order>>> = match side {
+                Side::Buy => Box::new(range.rev().map(|(_, queue)| queue)),
+                Side::Sell => Box::new(range.map(|(_, queue)| queue)),
+            };
+            // Queues come best offset first, so those sharing the top
+            // price once capped are the leading ones.
+            let mut top_price = None;
+            for queue in queues {
+                let Some(order) = queue.front() else { continue };
+                let price = order.peg.and_then(|peg| peg.price(side, top));
+                match (price, top_price) {
+                    (None, None) => continue,
+                    (Some(price), None) => top_price = Some(price),
+                    (Some(price), Some(first)) if price == first => {}
+                    _ => break,
+                }
+                let price = price.expect("priced above");
+                if best.is_none_or(|(best_price, best_order)| rank(price, order) < rank(best_price, best_order)) {
+                    best = Some((price, order));
+                }
+            }
+        }
+        best
+    }
+
+    fn side(&self, side: Side) -> &BTreeMap<PegKey, VecDeque<Order>> {
+        match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<PegKey, VecDeque<Order>> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
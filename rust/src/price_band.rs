@@ -0,0 +1,98 @@
+//! Fat-finger and volatility protection around a reference price.
+use crate::order::BASIS_POINTS_PER_UNIT;
+use crate::units::Price;
+
+/// Distance from a reference price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandWidth {
+    Ticks(u64),
+    BasisPoints(u64),
+}
+
+impl BandWidth {
+    /// How far the band reaches either side of `reference`, or `None` if
+    /// that is beyond any price.
+    pub fn distance(self, reference: Price, tick_size: Price) -> Option<Price> {
+        let distance = match self {
+            BandWidth::Ticks(ticks) => i64::try_from(ticks).ok()?.checked_mul(tick_size.raw())?,
+            BandWidth::BasisPoints(bps) => {
+                let distance = reference.raw().unsigned_abs() as u128 * bps as u128 / BASIS_POINTS_PER_UNIT as u128;
+                i64::try_from(distance).ok()?
+            }
+        };
+        Some(Price::new(distance))
+    }
+
+    /// Lowest and highest price inside the band around `reference`,
+    /// inclusive. Basis points are taken of the reference's magnitude, so
+    /// the band stays centred on negative references too. A band wider
+    /// than the price range covers every price.
+    pub fn range(self, reference: Price, tick_size: Price) -> (Price, Price) {
+        let distance = self.distance(reference, tick_size).unwrap_or(Price::MAX);
+        (reference.saturating_sub(distance), reference.saturating_add(distance))
+    }
+}
+
+/// What the book does when a trade would leave the volatility band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterruptionAction {
+    /// Halt the session; the rest of the incoming order is cancelled.
+    Halt,
+    /// Switch to a call auction that uncrosses `duration` nanoseconds
+    /// later, as a scheduled session transition. The rest of the
+    /// incoming order joins the auction if it can rest.
+    Auction { duration: u64 },
+}
+
+/// Stops matching before a trade priced outside `width` of the last trade
+/// price seen when the incoming order arrived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VolatilityInterruption {
+    pub width: BandWidth,
+    pub action: InterruptionAction,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PriceBands {
+    /// Limit prices outside this band around the static reference are
+    /// rejected.
+    pub static_band: Option<BandWidth>,
+    /// Static reference price, such as the previous close. Defaults to the
+    /// last trade price.
+    pub reference_price: Option<Price>,
+    pub volatility: Option<VolatilityInterruption>,
+}
+
+impl PriceBands {
+    /// The first width counted in ticks that is too wide to measure in
+    /// half-ticks of `tick_size`.
+    pub fn too_wide(&self, tick_size: Price) -> Option<BandWidth> {
+        let half_tick = tick_size.checked_add(tick_size);
+        let widths = self.static_band.into_iter().chain(self.volatility.map(|volatility| volatility.width));
+        widths
+            .filter(|width| matches!(width, BandWidth::Ticks(_)))
+            .find(|width| half_tick.and_then(|half_tick| width.distance(Price::ZERO, half_tick)).is_none())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BandWidth;
+    use crate::units::Price;
+
+    fn range(width: BandWidth, reference: i64, tick_size: i64) -> (i64, i64) {
+        let (low, high) = width.range(Price::new(reference), Price::new(tick_size));
+        (low.raw(), high.raw())
+    }
+
+    #[test]
+    fn band_widths_around_reference() {
+        assert_eq!(range(BandWidth::Ticks(5), 100, 1), (95, 105));
+        assert_eq!(range(BandWidth::Ticks(5), 3, 1), (-2, 8));
+        assert_eq!(range(BandWidth::Ticks(2), 100, 5), (90, 110));
+        assert_eq!(range(BandWidth::BasisPoints(1_000), 250, 1), (225, 275));
+        assert_eq!(range(BandWidth::BasisPoints(1_000), -250, 1), (-275, -225));
+        assert_eq!(range(BandWidth::Ticks(u64::MAX), 0, 1), (-i64::MAX, i64::MAX));
+        assert_eq!(range(BandWidth::BasisPoints(u64::MAX), i64::MAX, 1), (0, i64::MAX));
+    }
+}
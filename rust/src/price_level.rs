@@ -0,0 +1,165 @@
+//! FIFO queue of resting orders at a single price.
+//!
+//! Orders live in a slab (`OrderArena`) shared by every level of a book and
+//! are chained into per-level doubly linked lists, so an order can be
+//! unlinked from anywhere in its queue in O(1) given its `OrderHandle`.
+use crate::order::Order;
+use crate::units::Qty;
+
+/// Stable location of a resting order inside an `OrderArena`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OrderHandle(usize);
+
+struct OrderNode {
+    order: Order,
+    prev: Option<OrderHandle>,
+    next: Option<OrderHandle>,
+}
+
+#[derive(Default)]
+pub struct OrderArena {
+    nodes: Vec<Option<OrderNode>>,
+    free: Vec<usize>,
+}
+
+impl OrderArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, handle: OrderHandle) -> &Order {
+        &self.node(handle).order
+    }
+
+    fn node(&self, handle: OrderHandle) -> &OrderNode {
+        self.nodes[handle.0].as_ref().expect("stale order handle")
+    }
+
+    fn node_mut(&mut self, handle: OrderHandle) -> &mut OrderNode {
+        self.nodes[handle.0].as_mut().expect("stale order handle")
+    }
+
+    fn insert(&mut self, node: OrderNode) -> OrderHandle {
+        match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = Some(node);
+                OrderHandle(slot)
+            }
+            None => {
+                self.nodes.push(Some(node));
+                OrderHandle(self.nodes.len() - 1)
+            }
+        }
+    }
+
+    fn remove(&mut self, handle: OrderHandle) -> OrderNode {
+        let node = self.nodes[handle.0].take().expect("stale order handle");
+        self.free.push(handle.0);
+        node
+    }
+}
+
+pub struct PriceLevel {
+    /// Displayed quantity; iceberg reserves are tracked in `hidden_quantity`.
+    pub total_quantity: Qty,
+    pub hidden_quantity: Qty,
+    len: usize,
+    head: Option<OrderHandle>,
+    tail: Option<OrderHandle>,
+}
+
+impl Default for PriceLevel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PriceLevel {
+    pub fn new() -> Self {
+        PriceLevel {
+            total_quantity: Qty::ZERO,
+            hidden_quantity: Qty::ZERO,
+            len: 0,
+            head: None,
+            tail: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The order with time priority at this level.
+    pub fn front(&self) -> Option<OrderHandle> {
+        self.head
+    }
+
+    pub fn push_back(&mut self, arena: &mut OrderArena, order: Order) -> OrderHandle {
+        self.total_quantity = self.total_quantity.saturating_add(order.quantity);
+        self.hidden_quantity = self.hidden_quantity.saturating_add(order.hidden_quantity);
+        let handle = arena.insert(OrderNode {
+            order,
+            prev: self.tail,
+            next: None,
+        });
+        match self.tail {
+            Some(tail) => arena.node_mut(tail).next = Some(handle),
+            None => self.head = Some(handle),
+        }
+        self.tail = Some(handle);
+        self.len += 1;
+        handle
+    }
+
+    /// Unlinks the order from this level and releases its slot.
+    pub fn remove(&mut self, arena: &mut OrderArena, handle: OrderHandle) -> Order {
+        let node = arena.remove(handle);
+        match node.prev {
+            Some(prev) => arena.node_mut(prev).next = node.next,
+            None => self.head = node.next,
+        }
+        match node.next {
+            Some(next) => arena.node_mut(next).prev = node.prev,
+            None => self.tail = node.prev,
+        }
+        self.total_quantity = self.total_quantity.saturating_sub(node.order.quantity);
+        self.hidden_quantity = self.hidden_quantity.saturating_sub(node.order.hidden_quantity);
+        self.len -= 1;
+        node.order
+    }
+
+    /// Changes an order's displayed quantity in place, keeping its queue position.
+    pub fn set_quantity(&mut self, arena: &mut OrderArena, handle: OrderHandle, quantity: Qty) {
+        self.update(arena, handle, |order| order.quantity = quantity);
+    }
+
+    /// Edits an order in place, keeping its queue position and the level totals.
+    pub fn update(&mut self, arena: &mut OrderArena, handle: OrderHandle, edit: impl FnOnce(&mut Order)) {
+        let order = &mut arena.node_mut(handle).order;
+        self.total_quantity = self.total_quantity.saturating_sub(order.quantity);
+        self.hidden_quantity = self.hidden_quantity.saturating_sub(order.hidden_quantity);
+        edit(order);
+        self.total_quantity = self.total_quantity.saturating_add(order.quantity);
+        self.hidden_quantity = self.hidden_quantity.saturating_add(order.hidden_quantity);
+    }
+
+    /// Orders at this level in time priority.
+    pub fn iter<'a>(&self, arena: &'a OrderArena) -> impl Iterator<Item = &'a Order> + 'a {
+        self.entries(arena).map(|(_, order)| order)
+    }
+
+    /// Like `iter`, with each order's handle.
+    pub fn entries<'a>(&self, arena: &'a OrderArena) -> impl Iterator<Item = (OrderHandle, &'a Order)> + 'a {
+        let mut cursor = self.head;
+        std::iter::from_fn(move || {
+            let handle = cursor?;
+            let node = arena.node(handle);
+            cursor = node.next;
+            Some((handle, &node.order))
+        })
+    }
+}
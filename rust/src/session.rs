@@ -0,0 +1,86 @@
+//! Trading session states and which operations each one allows.
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SessionState {
+    /// Orders are collected into a call auction without matching.
+    PreOpen,
+    Continuous,
+    /// Trading is suspended; resting orders can only be cancelled.
+    Halted,
+    Closed,
+}
+
+/// Order operations gated by the session state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    AddOrder,
+    ModifyOrder,
+    CancelOrder,
+}
+
+impl SessionState {
+    pub fn allows(self, operation: Operation) -> bool {
+        match self {
+            SessionState::PreOpen | SessionState::Continuous => true,
+            SessionState::Halted => operation == Operation::CancelOrder,
+            SessionState::Closed => false,
+        }
+    }
+
+    pub fn can_transition_to(self, next: SessionState) -> bool {
+        use SessionState::*;
+        matches!(
+            (self, next),
+            (Closed, PreOpen)
+                | (PreOpen, Continuous | Halted | Closed)
+                | (Continuous, Halted | Closed)
+                | (Halted, PreOpen | Continuous | Closed)
+        )
+    }
+}
+
+impl fmt::Display for SessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SessionState::PreOpen => "pre-open",
+            SessionState::Continuous => "continuous",
+            SessionState::Halted => "halted",
+            SessionState::Closed => "closed",
+        };
+        f.write_str(name)
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::AddOrder => "add orders",
+            Operation::ModifyOrder => "modify orders",
+            Operation::CancelOrder => "cancel orders",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Operation, SessionState};
+
+    #[test]
+    fn halted_session_only_accepts_cancels() {
+        let halted = SessionState::Halted;
+        assert!(halted.allows(Operation::CancelOrder));
+        assert!(!halted.allows(Operation::AddOrder));
+        assert!(!halted.allows(Operation::ModifyOrder));
+        assert!(!SessionState::Closed.allows(Operation::CancelOrder));
+    }
+
+    #[test]
+    fn closed_session_reopens_through_pre_open() {
+        assert!(!SessionState::Closed.can_transition_to(SessionState::Continuous));
+        assert!(SessionState::Closed.can_transition_to(SessionState::PreOpen));
+        assert!(!SessionState::Continuous.can_transition_to(SessionState::PreOpen));
+        assert!(!SessionState::Halted.can_transition_to(SessionState::Halted));
+    }
+}
@@ -0,0 +1,170 @@
+//! Holds stop and stop-limit orders until the last trade price reaches
+//! their stop price. Trade prices are given in half-ticks so that midpoint
+//! trades between two ticks are compared exactly.
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::order::{Order, Side};
+use crate::peg_book::half_ticks;
+use crate::units::{Price, Qty};
+
+/// Whether a trade at `last_price` half-ticks elects a stop order.
+pub fn is_triggered(order: &Order, last_price: i64) -> bool {
+    match order.side {
+        Side::Buy => last_price >= half_ticks(order.stop_price),
+        Side::Sell => last_price <= half_ticks(order.stop_price),
+    }
+}
+
+/// The tick price a trailing stop on `side` trails from after a trade at
+/// `last_price` half-ticks. A trade between two ticks counts as the tick
+/// further from where the stop sits, so the stop keeps at least its full
+/// distance from the trade.
+pub fn trailing_reference(side: Side, last_price: i64) -> Price {
+    match side {
+        Side::Buy => Price::new(last_price.div_euclid(2) + last_price.rem_euclid(2)),
+        Side::Sell => Price::new(last_price.div_euclid(2)),
+    }
+}
+
+#[derive(Default)]
+pub struct StopBook {
+    buy_stops: BTreeMap<Price, VecDeque<Order>>,
+    sell_stops: BTreeMap<Price, VecDeque<Order>>,
+    index: HashMap<u64, (Side, Price)>,
+    trailing: Vec<u64>,
+}
+
+impl StopBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, order_id: u64) -> bool {
+        self.index.contains_key(&order_id)
+    }
+
+    pub fn get(&self, order_id: u64) -> Option<&Order> {
+        let (side, stop_price) = self.index.get(&order_id)?;
+        self.side(*side)[stop_price].iter().find(|o| o.order_id == order_id)
+    }
+
+    pub fn insert(&mut self, order: Order) {
+        if order.trailing_offset.is_some() {
+            self.trailing.push(order.order_id);
+        }
+        self.place(order);
+    }
+
+    pub fn remove(&mut self, order_id: u64) -> Option<Order> {
+        let order = self.take(order_id)?;
+        if order.trailing_offset.is_some() {
+            self.trailing.retain(|&id| id != order_id);
+        }
+        Some(order)
+    }
+
+    /// Every parked stop, in no particular order.
+    pub fn orders(&self) -> impl Iterator<Item = &Order> + '_ {
+        self.buy_stops.values().chain(self.sell_stops.values()).flatten()
+    }
+
+    /// Changes a parked stop's quantity; it keeps its place in the queue.
+    pub fn set_quantity(&mut self, order_id: u64, quantity: Qty) -> bool {
+        let Some(&(side, stop_price)) = self.index.get(&order_id) else { return false };
+        let queue = self.side_mut(side).get_mut(&stop_price).expect("indexed stop");
+        let order = queue.iter_mut().find(|o| o.order_id == order_id).expect("indexed stop");
+        order.quantity = quantity;
+        true
+    }
+
+    /// Ratchets trailing stops towards a trade at `last_price` half-ticks:
+    /// sell stops only move up, buy stops only move down. A repriced stop
+    /// queues behind stops already at its new price.
+    pub fn update_trailing(&mut self, last_price: i64) {
+        for i in 0..self.trailing.len() {
+            let order_id = self.trailing[i];
+            let (side, stop_price) = self.index[&order_id];
+            let queue = &self.side(side)[&stop_price];
+            let order = queue.iter().find(|o| o.order_id == order_id).expect("indexed stop");
+            let reference = trailing_reference(side, last_price);
+            let Some(trailed) = order.trailing_stop_price(reference) else { continue };
+            let tighter = match side {
+                Side::Buy => trailed < stop_price,
+                Side::Sell => trailed > stop_price,
+            };
+            if tighter {
+                let mut order = self.take(order_id).expect("indexed stop");
+                order.stop_price = trailed;
+                self.place(order);
+            }
+        }
+    }
+
+    fn place(&mut self, order: Order) {
+        self.index.insert(order.order_id, (order.side, order.stop_price));
+        self.side_mut(order.side)
+            .entry(order.stop_price)
+            .or_default()
+            .push_back(order);
+    }
+
+    fn take(&mut self, order_id: u64) -> Option<Order> {
+        let (side, stop_price) = self.index.remove(&order_id)?;
+        let stops = self.side_mut(side);
+        let queue = stops.get_mut(&stop_price)?;
+        let position = queue.iter().position(|o| o.order_id == order_id)?;
+        let order = queue.remove(position);
+        if queue.is_empty() {
+            stops.remove(&stop_price);
+        }
+        order
+    }
+
+    /// Removes every stop elected by a trade at `last_price` half-ticks. Buy
+    /// stops come first, lowest stop price first; then sell stops, highest
+    /// stop price first. Stops sharing a price keep their arrival order.
+    pub fn take_triggered(&mut self, last_price: i64) -> Vec<Order> {
+        let mut triggered = Vec::new();
+        while let Some(entry) = self.buy_stops.first_entry() {
+            if half_ticks(*entry.key()) > last_price {
+                break;
+            }
+            triggered.extend(entry.remove());
+        }
+        while let Some(entry) = self.sell_stops.last_entry() {
+            if half_ticks(*entry.key()) < last_price {
+                break;
+            }
+            triggered.extend(entry.remove());
+        }
+        for order in &triggered {
+            self.index.remove(&order.order_id);
+        }
+        if triggered.iter().any(|o| o.trailing_offset.is_some()) {
+            self.trailing.retain(|id| self.index.contains_key(id));
+        }
+        triggered
+    }
+
+    fn side(&self, side: Side) -> &BTreeMap<Price, VecDeque<Order>> {
+        match side {
+            Side::Buy => &self.buy_stops,
+            Side::Sell => &self.sell_stops,
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Price, VecDeque<Order>> {
+        match side {
+            Side::Buy => &mut self.buy_stops,
+            Side::Sell => &mut self.sell_stops,
+        }
+    }
+}
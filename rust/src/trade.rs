@@ -1,22 +1,34 @@
 //! Represents a trade that has occurred.
+use crate::units::{Price, Qty};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Trade {
+    /// Numbered from 1 by the book that executed it.
+    pub trade_id: u64,
     pub taker_order_id: u64,
     pub maker_order_id: u64,
-    pub quantity: u64,
-    pub price: u64,
+    pub quantity: Qty,
+    pub price: Price,
+    /// The execution happened half a tick above `price` (midpoint pegs).
+    pub half_tick: bool,
     pub timestamp: u64,
 }
 
 impl Trade {
-    pub fn new(taker_order_id: u64, maker_order_id: u64, quantity: u64, price: u64) -> Self {
+    pub fn new(taker_order_id: u64, maker_order_id: u64, quantity: Qty, price: Price) -> Self {
         Trade {
+            trade_id: 0, // Will be set by the book
             taker_order_id,
             maker_order_id,
             quantity,
             price,
+            half_tick: false,
             timestamp: 0, // Will be set by the matching engine
         }
     }
+
+    /// The exact execution price in half-ticks.
+    pub fn half_tick_price(&self) -> i64 {
+        2 * self.price.raw() + self.half_tick as i64
+    }
 }
@@ -0,0 +1,301 @@
+//! Fixed-point price and quantity types.
+//!
+//! `Price` and `Qty` count whole minimum units (ticks of the price scale,
+//! single lots) so the book never touches floating point. Prices are
+//! signed, for spreads and instruments that trade below zero. Arithmetic
+//! operators panic instead of wrapping; use the `checked_*` and
+//! `saturating_*` methods where overflow is expected. `Decimal` converts
+//! them to and from exact decimal strings at a given scale.
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+macro_rules! fixed_point {
+    ($name:ident, $units:ty, $what:literal) => {
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+        #[serde(transparent)]
+        pub struct $name($units);
+
+        impl $name {
+            pub const ZERO: $name = $name(0);
+            pub const MIN: $name = $name(<$units>::MIN);
+            pub const MAX: $name = $name(<$units>::MAX);
+
+            pub const fn new(units: $units) -> Self {
+                $name(units)
+            }
+
+            pub const fn raw(self) -> $units {
+                self.0
+            }
+
+            pub const fn is_zero(self) -> bool {
+                self.0 == 0
+            }
+
+            pub fn checked_add(self, other: Self) -> Option<Self> {
+                self.0.checked_add(other.0).map($name)
+            }
+
+            pub fn checked_sub(self, other: Self) -> Option<Self> {
+                self.0.checked_sub(other.0).map($name)
+            }
+
+            pub fn saturating_add(self, other: Self) -> Self {
+                $name(self.0.saturating_add(other.0))
+            }
+
+            pub fn saturating_sub(self, other: Self) -> Self {
+                $name(self.0.saturating_sub(other.0))
+            }
+
+            /// The value as a decimal with `scale` implied decimal places.
+            pub fn to_decimal(self, scale: u32) -> Decimal {
+                Decimal::new(self.0 as i128, scale)
+            }
+
+            /// The value of `decimal` in units of `scale` decimal places, or
+            /// `None` if it is out of range or more precise than that.
+            pub fn from_decimal(decimal: Decimal, scale: u32) -> Option<Self> {
+                let units = decimal.rescale(scale)?;
+                <$units>::try_from(units).ok().map($name)
+            }
+        }
+
+        impl From<$units> for $name {
+            fn from(units: $units) -> Self {
+                $name(units)
+            }
+        }
+
+        impl PartialEq<$units> for $name {
+            fn eq(&self, other: &$units) -> bool {
+                self.0 == *other
+            }
+        }
+
+        impl PartialOrd<$units> for $name {
+            fn partial_cmp(&self, other: &$units) -> Option<std::cmp::Ordering> {
+                self.0.partial_cmp(other)
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            fn add(self, other: $name) -> $name {
+                self.checked_add(other).expect(concat!($what, " overflow"))
+            }
+        }
+
+        impl Sub for $name {
+            type Output = $name;
+
+            fn sub(self, other: $name) -> $name {
+                self.checked_sub(other).expect(concat!($what, " underflow"))
+            }
+        }
+
+        impl AddAssign for $name {
+            fn add_assign(&mut self, other: $name) {
+                *self = *self + other;
+            }
+        }
+
+        impl SubAssign for $name {
+            fn sub_assign(&mut self, other: $name) {
+                *self = *self - other;
+            }
+        }
+
+        impl Sum for $name {
+            fn sum<I: Iterator<Item = $name>>(iter: I) -> $name {
+                iter.fold($name::ZERO, Add::add)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+    };
+}
+
+fixed_point!(Price, i64, "price");
+fixed_point!(Qty, u64, "quantity");
+
+impl Price {
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Distance between two prices, in price units.
+    pub const fn abs_diff(self, other: Price) -> u64 {
+        self.0.abs_diff(other.0)
+    }
+}
+
+/// An exact decimal number: `units / 10^scale`. Serializes as a string
+/// such as `"101.25"` so no precision is lost on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    units: i128,
+    scale: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError(String);
+
+impl Decimal {
+    pub const fn new(units: i128, scale: u32) -> Self {
+        Decimal { units, scale }
+    }
+
+    pub const fn units(self) -> i128 {
+        self.units
+    }
+
+    pub const fn scale(self) -> u32 {
+        self.scale
+    }
+
+    /// Units at `scale` decimal places, or `None` if that would drop
+    /// non-zero digits or overflow.
+    pub fn rescale(self, scale: u32) -> Option<i128> {
+        if scale >= self.scale {
+            self.units.checked_mul(10i128.checked_pow(scale - self.scale)?)
+        } else {
+            let divisor = 10i128.checked_pow(self.scale - scale)?;
+            (self.units % divisor == 0).then_some(self.units / divisor)
+        }
+    }
+
+    /// Nearest `f64`, for display and charting only.
+    pub fn to_f64(self) -> f64 {
+        self.units as f64 / 10f64.powi(self.scale as i32)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.units < 0 { "-" } else { "" };
+        let magnitude = self.units.unsigned_abs();
+        if self.scale == 0 {
+            return write!(f, "{}{}", sign, magnitude);
+        }
+        // Formatted from the digits so that any scale works, including
+        // ones beyond the largest power of ten an integer can hold.
+        let scale = self.scale as usize;
+        let digits = format!("{:0width$}", magnitude, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+
+    /// Parses `[-]digits[.digits]`; the scale is the number of fraction
+    /// digits given.
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseDecimalError(text.to_string());
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if whole.is_empty() || !all_digits(whole) || !all_digits(fraction) || digits.ends_with('.') {
+            return Err(invalid());
+        }
+        let units: i128 = format!("{}{}", whole, fraction).parse().map_err(|_| invalid())?;
+        Ok(Decimal {
+            units: if negative { -units } else { units },
+            scale: fraction.len() as u32,
+        })
+    }
+}
+
+impl fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid decimal {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseDecimalError {}
+
+impl Serialize for Decimal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decimal, Price, Qty};
+
+    #[test]
+    fn checked_and_saturating_arithmetic() {
+        let five = Qty::new(5);
+        assert_eq!(five.checked_sub(Qty::new(6)), None);
+        assert_eq!(five.saturating_sub(Qty::new(6)), Qty::ZERO);
+        assert_eq!(Qty::MAX.checked_add(five), None);
+        assert_eq!(five + Qty::new(2), 7);
+        assert_eq!([five, five].into_iter().sum::<Qty>(), 10);
+        assert!(Price::new(101) > 100);
+        assert_eq!(Price::new(-3) + Price::new(5), 2);
+        assert_eq!(Price::MIN.checked_sub(Price::new(1)), None);
+        assert_eq!(Price::new(-3).abs_diff(Price::new(4)), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "quantity underflow")]
+    fn operators_never_wrap() {
+        let mut total = Qty::new(1);
+        total -= Qty::new(2);
+    }
+
+    #[test]
+    fn formats_exact_decimals() {
+        assert_eq!(Price::new(10_125).to_decimal(2).to_string(), "101.25");
+        assert_eq!(Price::new(7).to_decimal(3).to_string(), "0.007");
+        assert_eq!(Price::new(42).to_decimal(0).to_string(), "42");
+        assert_eq!(Decimal::new(-5, 2).to_string(), "-0.05");
+        assert_eq!(Decimal::new(12, 40).to_string(), format!("0.{}12", "0".repeat(38)));
+        let tiny = format!("0.{}5", "0".repeat(39));
+        assert_eq!(tiny.parse::<Decimal>().unwrap().to_string(), tiny);
+        assert_eq!(Price::new(-1_250).to_decimal(2).to_string(), "-12.50");
+    }
+
+    #[test]
+    fn parses_and_rescales_without_rounding() {
+        let decimal: Decimal = "101.2".parse().unwrap();
+        assert_eq!(Price::from_decimal(decimal, 2), Some(Price::new(10_120)));
+        assert_eq!(Price::from_decimal(decimal, 0), None);
+        assert_eq!(Price::from_decimal("-1.5".parse().unwrap(), 2), Some(Price::new(-150)));
+        assert_eq!(Qty::from_decimal("-1".parse().unwrap(), 0), None);
+        assert!("1.".parse::<Decimal>().is_err());
+        assert!("abc".parse::<Decimal>().is_err());
+        assert!(".5".parse::<Decimal>().is_err());
+    }
+
+    #[test]
+    fn serde_keeps_every_digit() {
+        let decimal = Price::new(12_345_678_901_234_567).to_decimal(8);
+        let json = serde_json::to_string(&decimal).unwrap();
+        assert_eq!(json, "\"123456789.01234567\"");
+        assert_eq!(serde_json::from_str::<Decimal>(&json).unwrap(), decimal);
+        assert_eq!(serde_json::to_string(&Qty::new(30)).unwrap(), "30");
+    }
+}
@@ -1,5 +1,13 @@
-use futures_util::{SinkExt, StreamExt};
-use serde_json;
+//! Streams the simulated market to websocket clients.
+//!
+//! A client first receives an `orderbook-snapshot` carrying every level
+//! and every resting order, with the `sequence` of the last level update
+//! and the `order_sequence` of the last order event it reflects, then an
+//! `orderbook-delta` for each simulation step that changed something.
+//! Level updates and order events are each numbered consecutively: a
+//! client skips ones at or below its sequence, and on any other jump
+//! reconnects to get a fresh snapshot.
+use futures_util::SinkExt;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
@@ -8,15 +16,10 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::time::interval;
 use tokio_tungstenite::{accept_async, tungstenite::Message, WebSocketStream};
 
-mod order;
-mod order_book;
-mod trade;
-mod concurrent_queue;
-mod market_simulator;
+use lock_free_order_book::clock::{Clock, MonotonicClock};
+use lock_free_order_book::market_simulator::MarketSimulator;
 
-use market_simulator::MarketSimulator;
-
-type Clients = Arc<Mutex<HashMap<SocketAddr, tokio_tungstenite::WebSocketStream<TcpStream>>>>;
+type Clients = Arc<tokio::sync::Mutex<HashMap<SocketAddr, WebSocketStream<TcpStream>>>>;
 
 #[tokio::main]
 async fn main() {
@@ -26,8 +29,9 @@ async fn main() {
     let listener = TcpListener::bind(&addr).await.expect("Failed to bind");
     println!("📡 WebSocket server listening on: ws://{}", addr);
     
-    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
-    let simulator = Arc::new(Mutex::new(MarketSimulator::new()));
+    let clients: Clients = Arc::new(tokio::sync::Mutex::new(HashMap::new()));
+    let clock: Arc<dyn Clock> = Arc::new(MonotonicClock::new());
+    let simulator = Arc::new(Mutex::new(MarketSimulator::with_clock(clock)));
     
     // Start market simulation task
     let clients_clone = clients.clone();
@@ -54,7 +58,7 @@ async fn handle_connection(
     clients: Clients,
     simulator: Arc<Mutex<MarketSimulator>>,
 ) {
-    let ws_stream = match accept_async(stream).await {
+    let mut ws_stream = match accept_async(stream).await {
         Ok(ws) => ws,
         Err(e) => {
             println!("❌ WebSocket connection error: {}", e);
@@ -64,22 +68,17 @@ async fn handle_connection(
     
     println!("✅ WebSocket connection established with {}", addr);
     
-    // Send initial snapshot
-    if let Ok(sim) = simulator.lock() {
-        let snapshot = sim.get_snapshot();
+    // Send the initial snapshot and join the broadcast while holding the
+    // client map, so no delta can be published between the two.
+    {
+        let mut clients_map = clients.lock().await;
+        let snapshot = simulator.lock().unwrap().get_snapshot();
         if let Ok(message) = serde_json::to_string(&snapshot) {
             let ws_message = Message::Text(format!(r#"{{"type":"orderbook-snapshot","data":{}}}"#, message));
-            if let Ok(mut clients_map) = clients.lock() {
-                if let Some(client) = clients_map.get_mut(&addr) {
-                    let _ = client.send(ws_message).await;
-                }
+            if ws_stream.send(ws_message).await.is_err() {
+                return;
             }
         }
-    }
-    
-    // Add client to the map
-    {
-        let mut clients_map = clients.lock().unwrap();
         clients_map.insert(addr, ws_stream);
     }
     
@@ -90,7 +89,7 @@ async fn handle_connection(
         // Check if client is still connected
         let mut should_remove = false;
         {
-            let mut clients_map = clients.lock().unwrap();
+            let mut clients_map = clients.lock().await;
             if let Some(client) = clients_map.get_mut(&addr) {
                 if client.send(Message::Ping(vec![])).await.is_err() {
                     should_remove = true;
@@ -99,7 +98,7 @@ async fn handle_connection(
         }
         
         if should_remove {
-            let mut clients_map = clients.lock().unwrap();
+            let mut clients_map = clients.lock().await;
             clients_map.remove(&addr);
             println!("🔌 Client {} disconnected", addr);
             break;
@@ -113,17 +112,20 @@ async fn market_simulation_task(clients: Clients, simulator: Arc<Mutex<MarketSim
     loop {
         interval.tick().await;
         
-        let snapshot = {
+        let delta = {
             let mut sim = simulator.lock().unwrap();
             sim.simulate_market_activity()
         };
+        if delta.is_empty() {
+            continue;
+        }
         
-        if let Ok(message) = serde_json::to_string(&snapshot) {
-            let ws_message = Message::Text(format!(r#"{{"type":"orderbook-update","data":{}}}"#, message));
+        if let Ok(message) = serde_json::to_string(&delta) {
+            let ws_message = Message::Text(format!(r#"{{"type":"orderbook-delta","data":{}}}"#, message));
             
             let mut clients_to_remove = Vec::new();
             {
-                let mut clients_map = clients.lock().unwrap();
+                let mut clients_map = clients.lock().await;
                 for (addr, client) in clients_map.iter_mut() {
                     if client.send(ws_message.clone()).await.is_err() {
                         clients_to_remove.push(*addr);